@@ -12,6 +12,14 @@ uniform vec3 recent_color;
 uniform vec3 past_color;
 uniform sampler2D spectrum;
 uniform sampler2D logo;
+// 0 if the logo is hidden
+uniform float logo_alpha;
+// the thumbnail of the current song set with set_image, fading from the previous one
+uniform sampler2D cover;
+uniform sampler2D cover_previous;
+uniform float cover_transition;
+// 0 (cheapest) to 2 (best), lowered by adaptive quality
+uniform int quality;
 
 out vec4 fragColor;
 
@@ -77,21 +85,39 @@ void main() {
 	logo_uv *= scale;
 	logo_uv = logo_uv - 0.5 * (scale - 1.);
 
-	// use the cartesian coordinates as fake normals for a spherical look
-	vec3 normal = vec3(logo_uv.x - .5, -logo_uv.y + 0.5, 1);
-	// increase curvature of the faked sphere
-	normal.xy *= 0.5;
-	normal = normalize(normal);
-	vec3 light = normalize(vec3(1, -1, 1));
-	vec3 reflected = normalize(2.0 * dot(normal, light) * normal - light);
-	// center the logo_uv to get faked button like normals
-	const float intensity = 0.75;
-	const float shinyness = 8.;
-	vec3 specular = vec3(1) * intensity * pow(max(0.0, dot(vec3(0, 0, 1), reflected)), shinyness);
-
+	vec3 specular = vec3(0);
+	// the fake lighting is skipped on the lowest quality
+	if (quality > 0) {
+		// use the cartesian coordinates as fake normals for a spherical look
+		vec3 normal = vec3(logo_uv.x - .5, -logo_uv.y + 0.5, 1);
+		// increase curvature of the faked sphere
+		normal.xy *= 0.5;
+		normal = normalize(normal);
+		vec3 light = normalize(vec3(1, -1, 1));
+		vec3 reflected = normalize(2.0 * dot(normal, light) * normal - light);
+		// center the logo_uv to get faked button like normals
+		const float intensity = 0.75;
+		const float shinyness = 8.;
+		specular = vec3(1) * intensity * pow(max(0.0, dot(vec3(0, 0, 1), reflected)), shinyness);
+	}
+
+	float logo_mask = 1. - smoothstep(logo_radius - FADE_DIST, logo_radius, polar.t);
 	vec3 tex_color = texture(logo, logo_uv).rgb;
-	rgb = mix(rgb, specular, 1. - smoothstep(logo_radius - FADE_DIST, logo_radius, polar.t));
+	// the cover replaces the logo, blended premultiplied so fading from or to no cover does not darken it
+	vec4 previous_cover = texture(cover_previous, logo_uv);
+	vec4 current_cover = texture(cover, logo_uv);
+	vec4 cover_color = mix(
+		vec4(previous_cover.rgb * previous_cover.a, previous_cover.a),
+		vec4(current_cover.rgb * current_cover.a, current_cover.a),
+		cover_transition);
+	// the button is only drawn behind a logo or a cover
+	float button_mask = logo_mask * max(logo_alpha, cover_color.a);
+	cover_color *= logo_mask;
+	tex_color = tex_color * (1. - cover_color.a) + cover_color.rgb;
+	rgb = mix(rgb, specular, button_mask);
 	rgb += tex_color;
+	// without a button the visualization behind the ring shows through
+	alpha *= 1. - logo_mask + button_mask;
 
 	//
 	// Postprocessing
@@ -9,7 +9,6 @@ in float speed;
 uniform float PARTICLE_SPAWN_Z;
 uniform float time_elapsed;
 uniform float intensity_fraction;
-uniform vec2 shake;
 
 out float age;
 
@@ -20,5 +19,4 @@ void main() {
 	age = 1. - z / PARTICLE_SPAWN_Z;
 
 	gl_Position = vec4(translation, 0.0, z);
-	gl_Position.xy += shake;
 }
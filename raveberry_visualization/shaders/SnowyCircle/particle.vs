@@ -9,7 +9,6 @@ in float speed;
 uniform float PARTICLE_SPAWN_Z;
 uniform float time_elapsed;
 uniform float intensity_fraction;
-uniform vec2 shake;
 
 out float age;
 out float rot;
@@ -31,5 +30,4 @@ void main() {
 	rot = (rand(z) - 0.5) * y;
 
 	gl_Position = vec4(x, y, 0.0, z);
-	gl_Position.xy += shake;
 }
@@ -0,0 +1,184 @@
+/// Settings for one step of the adaptive quality ladder.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QualityLevel {
+    /// multiplied with the configured render scale
+    pub render_scale: f32,
+    /// fraction of the particles that are drawn
+    pub particle_fraction: f32,
+    /// passed to the shaders as the `quality` uniform, lower is cheaper
+    pub shader_quality: i32,
+}
+
+/// Ordered from the best to the cheapest quality.
+/// Steps alternate between the options so quality degrades gradually.
+const LEVELS: [QualityLevel; 6] = [
+    QualityLevel {
+        render_scale: 1.0,
+        particle_fraction: 1.0,
+        shader_quality: 2,
+    },
+    QualityLevel {
+        render_scale: 1.0,
+        particle_fraction: 0.75,
+        shader_quality: 2,
+    },
+    QualityLevel {
+        render_scale: 0.85,
+        particle_fraction: 0.75,
+        shader_quality: 1,
+    },
+    QualityLevel {
+        render_scale: 0.75,
+        particle_fraction: 0.5,
+        shader_quality: 1,
+    },
+    QualityLevel {
+        render_scale: 0.6,
+        particle_fraction: 0.5,
+        shader_quality: 0,
+    },
+    QualityLevel {
+        render_scale: 0.5,
+        particle_fraction: 0.25,
+        shader_quality: 0,
+    },
+];
+
+// quality is lowered if the fps drop below this fraction of the target
+const LOWER_THRESHOLD: f32 = 0.9;
+// quality is raised if the fps stay above this fraction of the target
+const RAISE_THRESHOLD: f32 = 0.97;
+// how many consecutive good measurements are needed before quality is raised
+const INITIAL_RAISE_DELAY: u32 = 2;
+const MAX_RAISE_DELAY: u32 = 32;
+
+/// Adjusts the quality level based on the measured fps.
+///
+/// Since the frame rate is capped, there is no way to tell how much headroom there is.
+/// Instead, quality is raised tentatively after a few good measurements.
+/// If that immediately causes the fps to drop again, raising is delayed exponentially longer,
+/// so the controller settles instead of oscillating between two levels.
+pub struct AdaptiveQuality {
+    level: usize,
+    good_measurements: u32,
+    raise_delay: u32,
+    just_raised: bool,
+}
+
+impl AdaptiveQuality {
+    pub fn new() -> AdaptiveQuality {
+        AdaptiveQuality {
+            level: 0,
+            good_measurements: 0,
+            raise_delay: INITIAL_RAISE_DELAY,
+            just_raised: false,
+        }
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn current(&self) -> QualityLevel {
+        LEVELS[self.level]
+    }
+
+    /// Returns to the best quality, e.g. when adaptive mode is disabled.
+    pub fn reset(&mut self) {
+        *self = AdaptiveQuality::new();
+    }
+
+    /// Called once per fps measurement window.
+    pub fn update(&mut self, avg_fps: f32, target_fps: f32) {
+        if avg_fps < target_fps * LOWER_THRESHOLD {
+            if self.just_raised {
+                // the last raise was too much, wait longer before trying again
+                self.raise_delay = (self.raise_delay * 2).min(MAX_RAISE_DELAY);
+            }
+            self.level = (self.level + 1).min(LEVELS.len() - 1);
+            self.good_measurements = 0;
+            self.just_raised = false;
+            return;
+        }
+
+        if self.just_raised {
+            // the raise was sustainable
+            self.raise_delay = INITIAL_RAISE_DELAY;
+            self.just_raised = false;
+        }
+
+        if avg_fps >= target_fps * RAISE_THRESHOLD {
+            self.good_measurements += 1;
+        } else {
+            // within the hysteresis band, keep the current level
+            self.good_measurements = 0;
+        }
+        if self.good_measurements >= self.raise_delay && self.level > 0 {
+            self.level -= 1;
+            self.good_measurements = 0;
+            self.just_raised = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lowers_quality_when_fps_drop() {
+        let mut quality = AdaptiveQuality::new();
+        quality.update(50.0, 60.0);
+        assert_eq!(quality.level(), 1);
+        for _ in 0..10 {
+            quality.update(10.0, 60.0);
+        }
+        assert_eq!(quality.level(), LEVELS.len() - 1);
+    }
+
+    #[test]
+    fn keeps_level_within_hysteresis_band() {
+        let mut quality = AdaptiveQuality::new();
+        quality.update(50.0, 60.0);
+        for _ in 0..10 {
+            quality.update(56.0, 60.0);
+        }
+        assert_eq!(quality.level(), 1);
+    }
+
+    #[test]
+    fn raises_quality_after_good_measurements() {
+        let mut quality = AdaptiveQuality::new();
+        quality.update(50.0, 60.0);
+        quality.update(60.0, 60.0);
+        assert_eq!(quality.level(), 1);
+        quality.update(60.0, 60.0);
+        assert_eq!(quality.level(), 0);
+    }
+
+    #[test]
+    fn delays_raising_after_failed_raise() {
+        let mut quality = AdaptiveQuality::new();
+        quality.update(50.0, 60.0);
+        quality.update(60.0, 60.0);
+        quality.update(60.0, 60.0);
+        assert_eq!(quality.level(), 0);
+        // the raise was too much
+        quality.update(50.0, 60.0);
+        assert_eq!(quality.level(), 1);
+        for _ in 0..INITIAL_RAISE_DELAY * 2 - 1 {
+            quality.update(60.0, 60.0);
+            assert_eq!(quality.level(), 1);
+        }
+        quality.update(60.0, 60.0);
+        assert_eq!(quality.level(), 0);
+    }
+
+    #[test]
+    fn reset_returns_to_best_quality() {
+        let mut quality = AdaptiveQuality::new();
+        quality.update(10.0, 60.0);
+        quality.reset();
+        assert_eq!(quality.current(), LEVELS[0]);
+    }
+}
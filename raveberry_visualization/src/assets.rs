@@ -0,0 +1,51 @@
+use image::RgbaImage;
+use std::path::Path;
+
+/// A variant compiled into the library, used if it is not found on disk.
+pub struct BuiltinVariant {
+    pub name: &'static str,
+    /// the sources of the shaders, in the order of `variants::SHADER_FILES`
+    pub shaders: [&'static str; 5],
+    pub manifest: &'static str,
+}
+
+macro_rules! builtin_variant {
+    ($name:literal) => {
+        BuiltinVariant {
+            name: $name,
+            shaders: [
+                include_str!(concat!("../shaders/", $name, "/quad.vs")),
+                include_str!(concat!("../shaders/", $name, "/background.fs")),
+                include_str!(concat!("../shaders/", $name, "/foreground.fs")),
+                include_str!(concat!("../shaders/", $name, "/particle.vs")),
+                include_str!(concat!("../shaders/", $name, "/particle.fs")),
+            ],
+            manifest: include_str!(concat!("../shaders/", $name, "/variant.toml")),
+        }
+    };
+}
+
+pub const BUILTIN_VARIANTS: &[BuiltinVariant] =
+    &[builtin_variant!("Circle"), builtin_variant!("SnowyCircle")];
+
+const DEFAULT_LOGO: &[u8] = include_bytes!("../images/raveberry.png");
+
+pub fn builtin_variant(name: &str) -> Option<&'static BuiltinVariant> {
+    BUILTIN_VARIANTS.iter().find(|variant| variant.name == name)
+}
+
+/// Loads the raveberry logo from the images directory of the module root,
+/// falling back to the one compiled into the library.
+pub fn default_logo(module_root: &str) -> Result<RgbaImage, String> {
+    let path = Path::new(module_root).join("images/raveberry.png");
+    let image = if path.is_file() {
+        image::io::Reader::open(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|reader| reader.decode().map_err(|e| e.to_string()))
+            .map_err(|e| format!("Could not load {}: {}", path.display(), e))?
+    } else {
+        image::load_from_memory(DEFAULT_LOGO)
+            .map_err(|e| format!("Could not load the builtin logo: {}", e))?
+    };
+    Ok(image.to_rgba8())
+}
@@ -0,0 +1,319 @@
+use crate::overlay::Quad;
+use crate::text::{TextDraw, TextOverlay};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Seconds a banner takes to slide in and out.
+const ANIMATION_DURATION: f32 = 0.4;
+/// The height of the banner text as a fraction of the output height.
+const TEXT_SIZE: f32 = 0.05;
+/// The height of a banner relative to its text.
+const PADDING: f32 = 1.8;
+
+/// How a banner looks and how urgent it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BannerStyle {
+    Info,
+    /// preempts info banners
+    Alarm,
+}
+
+impl BannerStyle {
+    fn priority(&self) -> u32 {
+        match self {
+            BannerStyle::Info => 0,
+            BannerStyle::Alarm => 1,
+        }
+    }
+
+    fn background(&self) -> [f32; 4] {
+        match self {
+            BannerStyle::Info => [0.05, 0.05, 0.05, 0.8],
+            BannerStyle::Alarm => [0.8, 0.05, 0.05, 0.9],
+        }
+    }
+}
+
+impl FromStr for BannerStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "info" => Ok(BannerStyle::Info),
+            "alarm" => Ok(BannerStyle::Alarm),
+            _ => Err(format!("Unknown style {}, expected info or alarm", s)),
+        }
+    }
+}
+
+/// A message passed to `Controller.notify`.
+#[derive(Clone, Debug)]
+pub struct Banner {
+    pub text: String,
+    /// seconds the banner is fully shown
+    pub duration: f32,
+    pub style: BannerStyle,
+}
+
+struct ShownBanner {
+    banner: Banner,
+    shown_at: f32,
+    // when the banner starts sliding out
+    exit_at: f32,
+    // set if a more important banner pushed this one out before it was shown for its duration
+    preempted: bool,
+}
+
+/// The banner of a single frame.
+#[derive(Clone, Debug)]
+pub struct BannerDraw {
+    pub text: String,
+    pub style: BannerStyle,
+    /// 0 while the banner is outside of the output, 1 once it slid in completely
+    pub progress: f32,
+    /// seconds since the banner started sliding in
+    pub shown_for: f32,
+}
+
+impl BannerDraw {
+    fn rect(&self) -> [f32; 4] {
+        let height = TEXT_SIZE * PADDING;
+        let top = -height * (1.0 - self.progress);
+        [0.0, top, 1.0, top + height]
+    }
+
+    /// The strip behind the text, spanning the top of the output.
+    pub fn background(&self) -> Quad {
+        let mut color = self.style.background();
+        color[3] *= self.progress;
+        Quad {
+            rect: self.rect(),
+            tex_rect: [0.0, 0.0, 1.0, 1.0],
+            color: color,
+        }
+    }
+
+    /// The text, centered on the strip and scrolling if it is too long.
+    pub fn text(&self) -> TextDraw {
+        let [_, top, _, bottom] = self.rect();
+        TextDraw {
+            overlay: Arc::new(TextOverlay {
+                text: self.text.clone(),
+                position: (0.5, (top + bottom) / 2.0),
+                anchor: (0.5, 0.5),
+                size: TEXT_SIZE,
+                color: [1.0, 1.0, 1.0, 1.0],
+                fade: 0.0,
+                max_width: Some(0.9),
+            }),
+            alpha: self.progress,
+            scroll_time: self.shown_for,
+        }
+    }
+}
+
+/// Shows one banner at a time, the most important and then the oldest one first.
+pub struct BannerQueue {
+    queue: Vec<Banner>,
+    current: Option<ShownBanner>,
+}
+
+impl BannerQueue {
+    pub fn new() -> BannerQueue {
+        BannerQueue {
+            queue: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// Queues the banner behind all banners that are at least as important.
+    pub fn push(&mut self, banner: Banner) {
+        self.insert(banner, false);
+    }
+
+    // Queues the banner behind the more important ones, and in front of or behind
+    // the equally important ones.
+    fn insert(&mut self, banner: Banner, first: bool) {
+        let priority = banner.style.priority();
+        let index = self
+            .queue
+            .iter()
+            .position(|b| {
+                b.style.priority() < priority || (first && b.style.priority() == priority)
+            })
+            .unwrap_or(self.queue.len());
+        self.queue.insert(index, banner);
+    }
+
+    /// Advances to the next banner once the current one slid out.
+    pub fn update(&mut self, seconds_elapsed: f32) {
+        if let Some(current) = &mut self.current {
+            let more_important = self
+                .queue
+                .first()
+                .map_or(false, |b| b.style.priority() > current.banner.style.priority());
+            if more_important && seconds_elapsed < current.exit_at {
+                // slide out from wherever the banner currently is
+                let progress = ((seconds_elapsed - current.shown_at) / ANIMATION_DURATION).min(1.0);
+                current.shorten_to_remaining(seconds_elapsed);
+                current.exit_at = seconds_elapsed - (1.0 - progress) * ANIMATION_DURATION;
+                current.preempted = true;
+            }
+        }
+        let finished = self
+            .current
+            .as_ref()
+            .map_or(false, |c| seconds_elapsed >= c.exit_at + ANIMATION_DURATION);
+        if finished {
+            let current = self.current.take().unwrap();
+            if current.preempted {
+                // shown again for the rest of its duration after the more important ones
+                self.insert(current.banner, true);
+            }
+        }
+        if self.current.is_none() && !self.queue.is_empty() {
+            let banner = self.queue.remove(0);
+            self.current = Some(ShownBanner {
+                exit_at: seconds_elapsed + ANIMATION_DURATION + banner.duration,
+                banner: banner,
+                shown_at: seconds_elapsed,
+                preempted: false,
+            });
+        }
+    }
+
+    pub fn current(&self, seconds_elapsed: f32) -> Option<BannerDraw> {
+        let current = self.current.as_ref()?;
+        let entry = (seconds_elapsed - current.shown_at) / ANIMATION_DURATION;
+        let exit = (current.exit_at + ANIMATION_DURATION - seconds_elapsed) / ANIMATION_DURATION;
+        let progress = entry.min(exit).max(0.0).min(1.0);
+        Some(BannerDraw {
+            text: current.banner.text.clone(),
+            style: current.banner.style,
+            progress: ease(progress),
+            shown_for: seconds_elapsed - current.shown_at,
+        })
+    }
+}
+
+impl ShownBanner {
+    // Shortens the banner to the time it was not shown yet, for when it is shown again.
+    fn shorten_to_remaining(&mut self, seconds_elapsed: f32) {
+        let shown = (seconds_elapsed - self.shown_at - ANIMATION_DURATION).max(0.0);
+        self.banner.duration = (self.banner.duration - shown).max(ANIMATION_DURATION);
+    }
+}
+
+// Slows the animation down towards its end.
+fn ease(progress: f32) -> f32 {
+    1.0 - (1.0 - progress) * (1.0 - progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn banner(text: &str, duration: f32, style: BannerStyle) -> Banner {
+        Banner {
+            text: text.to_string(),
+            duration: duration,
+            style: style,
+        }
+    }
+
+    fn shown(queue: &BannerQueue, seconds_elapsed: f32) -> Option<String> {
+        queue.current(seconds_elapsed).map(|b| b.text)
+    }
+
+    // the time a banner of the given duration takes from sliding in to being gone,
+    // with a little slack against rounding
+    fn lifetime(duration: f32) -> f32 {
+        duration + 2.0 * ANIMATION_DURATION + 1e-3
+    }
+
+    #[test]
+    fn shows_alarms_first_then_in_order() {
+        let mut queue = BannerQueue::new();
+        queue.push(banner("first", 1.0, BannerStyle::Info));
+        queue.push(banner("second", 1.0, BannerStyle::Info));
+        queue.push(banner("alarm", 1.0, BannerStyle::Alarm));
+        let mut time = 0.0;
+        for expected in ["alarm", "first", "second"].iter() {
+            queue.update(time);
+            assert_eq!(shown(&queue, time).as_deref(), Some(*expected));
+            time += lifetime(1.0);
+        }
+        queue.update(time);
+        assert_eq!(shown(&queue, time), None);
+    }
+
+    #[test]
+    fn slides_in_and_out() {
+        let mut queue = BannerQueue::new();
+        assert!(queue.current(0.0).is_none());
+        queue.push(banner("info", 1.0, BannerStyle::Info));
+        queue.update(10.0);
+        assert_eq!(queue.current(10.0).unwrap().progress, 0.0);
+        let sliding_in = queue.current(10.0 + ANIMATION_DURATION / 2.0).unwrap().progress;
+        assert!(sliding_in > 0.0 && sliding_in < 1.0);
+        assert_eq!(queue.current(10.0 + ANIMATION_DURATION).unwrap().progress, 1.0);
+        let exit = 10.0 + ANIMATION_DURATION + 1.0;
+        assert_eq!(queue.current(exit).unwrap().progress, 1.0);
+        let sliding_out = queue.current(exit + ANIMATION_DURATION / 2.0).unwrap().progress;
+        assert_eq!(sliding_out, sliding_in);
+        assert_eq!(queue.current(exit + ANIMATION_DURATION).unwrap().progress, 0.0);
+        assert_eq!(queue.current(12.0).unwrap().shown_for, 2.0);
+    }
+
+    #[test]
+    fn alarm_preempts_info_which_is_shown_again_for_the_rest() {
+        let mut queue = BannerQueue::new();
+        queue.push(banner("info", 5.0, BannerStyle::Info));
+        queue.update(0.0);
+        // fully shown for one second
+        let preempted_at = ANIMATION_DURATION + 1.0;
+        queue.push(banner("alarm", 1.0, BannerStyle::Alarm));
+        queue.update(preempted_at);
+        assert_eq!(shown(&queue, preempted_at).as_deref(), Some("info"));
+        let sliding_out = queue.current(preempted_at + ANIMATION_DURATION / 2.0).unwrap();
+        assert!(sliding_out.progress < 1.0);
+
+        let alarm_at = preempted_at + ANIMATION_DURATION + 1e-3;
+        queue.update(alarm_at);
+        assert_eq!(shown(&queue, alarm_at).as_deref(), Some("alarm"));
+
+        let resumed_at = alarm_at + lifetime(1.0);
+        queue.update(resumed_at);
+        assert_eq!(shown(&queue, resumed_at).as_deref(), Some("info"));
+        let remaining = queue.current.as_ref().unwrap().banner.duration;
+        assert!((remaining - 4.0).abs() < 1e-4, "{}", remaining);
+    }
+
+    #[test]
+    fn preempted_banner_stays_ahead_of_later_ones() {
+        let mut queue = BannerQueue::new();
+        queue.push(banner("first", 5.0, BannerStyle::Info));
+        queue.update(0.0);
+        queue.push(banner("second", 1.0, BannerStyle::Info));
+        queue.push(banner("alarm", 1.0, BannerStyle::Alarm));
+        queue.update(1.0);
+        let alarm_at = 1.0 + ANIMATION_DURATION + 1e-3;
+        queue.update(alarm_at);
+        assert_eq!(shown(&queue, alarm_at).as_deref(), Some("alarm"));
+        let resumed_at = alarm_at + lifetime(1.0);
+        queue.update(resumed_at);
+        assert_eq!(shown(&queue, resumed_at).as_deref(), Some("first"));
+    }
+
+    #[test]
+    fn info_does_not_preempt_info() {
+        let mut queue = BannerQueue::new();
+        queue.push(banner("first", 5.0, BannerStyle::Info));
+        queue.update(0.0);
+        queue.push(banner("second", 1.0, BannerStyle::Info));
+        queue.update(1.0);
+        assert_eq!(queue.current(1.0).unwrap().progress, 1.0);
+        queue.update(lifetime(5.0));
+        assert_eq!(shown(&queue, lifetime(5.0)).as_deref(), Some("second"));
+    }
+}
@@ -0,0 +1,625 @@
+use crate::input::InputSource;
+use crate::logo::LogoSource;
+use crate::monitor::{MonitorLayout, MonitorSelection};
+use crate::qr::Corner;
+use crate::pacing::FramePacing;
+use crate::MAX_PARTICLES;
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// How the hues of the visualization are chosen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Colors {
+    pub saturation: f32,
+    pub value: f32,
+    /// the hue the color cycle starts at, in turns
+    pub hue: f32,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors {
+            saturation: 0.6,
+            value: 0.7,
+            hue: 0.0,
+        }
+    }
+}
+
+/// Everything that can be configured through a config file.
+///
+/// ```toml
+/// variant = "Circle"
+/// ups = 30
+/// particles = 400
+/// fps_measure_window = 5
+/// # sigma of the gauss filter applied to the spectrum, 0 disables smoothing
+/// smoothing = 1.5
+/// # 1 is full brightness, 0 black
+/// brightness = 1.0
+/// # searched for variants before the user and package directories
+/// variant_paths = ["/opt/visualizations"]
+/// # "default", "none" or the path of an image shown in the middle,
+/// # a logo set at runtime is kept if not given
+/// logo = "default"
+///
+/// [colors]
+/// saturation = 0.6
+/// value = 0.7
+/// hue = 0.0
+///
+/// [monitor]
+/// indices = [0, 1]
+/// layout = "mirror"
+///
+/// [window]
+/// fullscreen = true
+/// size = [1280, 720]
+/// frame_pacing = "vsync"
+///
+/// [text]
+/// # TrueType or OpenType font for all text, a common system font is used if not given
+/// font = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf"
+///
+/// [qr]
+/// url = "http://raveberry.local"
+/// corner = "bottom_right"
+/// # side length as a fraction of the screen height
+/// size = 0.25
+/// # seconds the code is shown and hidden in turn, hidden = 0 always shows it
+/// visible = 20
+/// hidden = 10
+///
+/// [input]
+/// source = "synthetic"
+/// # additional udp listeners, each receiving one frame per datagram
+/// listen = ["0.0.0.0:7700"]
+/// ```
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub variant: String,
+    pub variant_paths: Vec<PathBuf>,
+    /// None keeps the current logo
+    pub logo: Option<LogoSource>,
+    pub ups: f32,
+    pub num_particles: u32,
+    pub fps_measure_window: f32,
+    pub smoothing: f32,
+    pub brightness: f32,
+    pub colors: Colors,
+    pub monitors: MonitorSelection,
+    pub fullscreen: bool,
+    pub window_size: (u32, u32),
+    pub frame_pacing: FramePacing,
+    pub font: Option<PathBuf>,
+    pub qr_code: Option<QrCodeConfig>,
+    /// None if the spectrum is only passed in through `set_parameters`
+    pub input: Option<InputSource>,
+    pub listeners: Vec<SocketAddr>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            variant: "Circle".to_string(),
+            variant_paths: Vec::new(),
+            logo: None,
+            ups: 30.0,
+            num_particles: 400,
+            fps_measure_window: 5.0,
+            smoothing: 1.5,
+            brightness: 1.0,
+            colors: Colors::default(),
+            monitors: MonitorSelection::default(),
+            fullscreen: true,
+            window_size: (1280, 720),
+            frame_pacing: FramePacing::default(),
+            font: None,
+            qr_code: None,
+            input: None,
+            listeners: Vec::new(),
+        }
+    }
+}
+
+/// The QR code shown in a corner of the visualization.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QrCodeConfig {
+    pub url: String,
+    pub corner: Corner,
+    pub size: f32,
+    pub visible: f32,
+    pub hidden: f32,
+}
+
+impl QrCodeConfig {
+    pub fn new(url: &str) -> QrCodeConfig {
+        QrCodeConfig {
+            url: url.to_string(),
+            corner: Corner::BottomRight,
+            size: 0.25,
+            visible: 20.0,
+            hidden: 10.0,
+        }
+    }
+}
+
+/// A config file that could not be read, pointing at the offending key if possible.
+#[derive(Debug)]
+pub struct ConfigError {
+    /// the dotted path of the key, empty if the file itself is invalid
+    pub key: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.key.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.key, self.message)
+        }
+    }
+}
+
+// A table of the config file together with its position in the file, for error messages.
+struct Table<'a> {
+    prefix: String,
+    table: &'a toml::value::Table,
+}
+
+impl<'a> Table<'a> {
+    fn error(&self, name: &str, message: String) -> ConfigError {
+        ConfigError {
+            key: format!("{}{}", self.prefix, name),
+            message: message,
+        }
+    }
+
+    fn check_keys(&self, known: &[&str]) -> Result<(), ConfigError> {
+        match self.table.keys().find(|key| !known.contains(&key.as_str())) {
+            Some(key) => Err(self.error(key, "unknown key".to_string())),
+            None => Ok(()),
+        }
+    }
+
+    fn table(&self, name: &str) -> Result<Option<Table<'a>>, ConfigError> {
+        match self.table.get(name) {
+            None => Ok(None),
+            Some(toml::Value::Table(table)) => Ok(Some(Table {
+                prefix: format!("{}{}.", self.prefix, name),
+                table: table,
+            })),
+            Some(_) => Err(self.error(name, "expected a table".to_string())),
+        }
+    }
+
+    fn float(&self, name: &str) -> Result<Option<f32>, ConfigError> {
+        match self.table.get(name) {
+            None => Ok(None),
+            Some(toml::Value::Float(value)) => Ok(Some(*value as f32)),
+            Some(toml::Value::Integer(value)) => Ok(Some(*value as f32)),
+            Some(_) => Err(self.error(name, "expected a number".to_string())),
+        }
+    }
+
+    fn positive_float(&self, name: &str) -> Result<Option<f32>, ConfigError> {
+        match self.float(name)? {
+            Some(value) if !(value > 0.0 && value.is_finite()) => Err(self.error(
+                name,
+                format!("must be positive and finite, got {}", value),
+            )),
+            value => Ok(value),
+        }
+    }
+
+    fn finite_float(&self, name: &str) -> Result<Option<f32>, ConfigError> {
+        match self.float(name)? {
+            Some(value) if !value.is_finite() => {
+                Err(self.error(name, format!("must be finite, got {}", value)))
+            }
+            value => Ok(value),
+        }
+    }
+
+    fn unit_float(&self, name: &str) -> Result<Option<f32>, ConfigError> {
+        match self.float(name)? {
+            Some(value) if !(value >= 0.0 && value <= 1.0) => Err(self.error(
+                name,
+                format!("must be between 0 and 1, got {}", value),
+            )),
+            value => Ok(value),
+        }
+    }
+
+    fn integer(&self, name: &str) -> Result<Option<u32>, ConfigError> {
+        match self.table.get(name) {
+            None => Ok(None),
+            Some(value) => to_u32(value)
+                .map(Some)
+                .ok_or_else(|| self.error(name, "expected a non-negative integer".to_string())),
+        }
+    }
+
+    fn bool(&self, name: &str) -> Result<Option<bool>, ConfigError> {
+        match self.table.get(name) {
+            None => Ok(None),
+            Some(toml::Value::Boolean(value)) => Ok(Some(*value)),
+            Some(_) => Err(self.error(name, "expected true or false".to_string())),
+        }
+    }
+
+    fn string(&self, name: &str) -> Result<Option<&'a str>, ConfigError> {
+        match self.table.get(name) {
+            None => Ok(None),
+            Some(toml::Value::String(value)) => Ok(Some(value)),
+            Some(_) => Err(self.error(name, "expected a string".to_string())),
+        }
+    }
+
+    fn array(&self, name: &str) -> Result<Option<&'a Vec<toml::Value>>, ConfigError> {
+        match self.table.get(name) {
+            None => Ok(None),
+            Some(toml::Value::Array(values)) => Ok(Some(values)),
+            Some(_) => Err(self.error(name, "expected an array".to_string())),
+        }
+    }
+
+    /// Parses a string value with `FromStr`, reporting its error at the key.
+    fn parsed<T: std::str::FromStr<Err = String>>(
+        &self,
+        name: &str,
+    ) -> Result<Option<T>, ConfigError> {
+        match self.string(name)? {
+            None => Ok(None),
+            Some(value) => value.parse::<T>().map(Some).map_err(|e| self.error(name, e)),
+        }
+    }
+}
+
+fn to_u32(value: &toml::Value) -> Option<u32> {
+    match value {
+        toml::Value::Integer(value) if *value >= 0 && *value <= u32::MAX as i64 => {
+            Some(*value as u32)
+        }
+        _ => None,
+    }
+}
+
+impl Config {
+    /// Reads the config file at the given path. Keys missing from the file keep their default.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        config.update_from_file(path)?;
+        Ok(config)
+    }
+
+    /// Overwrites the settings that are present in the config file at the given path.
+    pub fn update_from_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError {
+            key: String::new(),
+            message: format!("Could not read {}: {}", path.display(), e),
+        })?;
+        self.update_from_str(&text)
+    }
+
+    /// Overwrites the settings that are present in the given toml document.
+    /// Nothing is changed if the document is invalid.
+    pub fn update_from_str(&mut self, text: &str) -> Result<(), ConfigError> {
+        let table = toml::from_str::<toml::value::Table>(text).map_err(|e| ConfigError {
+            key: String::new(),
+            message: e.to_string(),
+        })?;
+        let root = Table {
+            prefix: String::new(),
+            table: &table,
+        };
+        root.check_keys(&[
+            "variant",
+            "variant_paths",
+            "logo",
+            "ups",
+            "particles",
+            "fps_measure_window",
+            "smoothing",
+            "brightness",
+            "colors",
+            "monitor",
+            "window",
+            "text",
+            "qr",
+            "input",
+        ])?;
+
+        let mut config = self.clone();
+        if let Some(variant) = root.string("variant")? {
+            config.variant = variant.to_string();
+        }
+        if let Some(paths) = root.array("variant_paths")? {
+            config.variant_paths = paths
+                .iter()
+                .map(|path| path.as_str().map(PathBuf::from))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| {
+                    root.error("variant_paths", "expected an array of paths".to_string())
+                })?;
+            if let Some(path) = config.variant_paths.iter().find(|path| !path.is_dir()) {
+                return Err(root.error(
+                    "variant_paths",
+                    format!("{} is not a directory", path.display()),
+                ));
+            }
+        }
+        if let Some(logo) = root.parsed::<LogoSource>("logo")? {
+            config.logo = Some(logo);
+        }
+        if let Some(ups) = root.positive_float("ups")? {
+            config.ups = ups;
+        }
+        if let Some(num_particles) = root.integer("particles")? {
+            if num_particles > MAX_PARTICLES {
+                return Err(root.error(
+                    "particles",
+                    format!("must be at most {}, got {}", MAX_PARTICLES, num_particles),
+                ));
+            }
+            config.num_particles = num_particles;
+        }
+        if let Some(fps_measure_window) = root.positive_float("fps_measure_window")? {
+            config.fps_measure_window = fps_measure_window;
+        }
+        if let Some(smoothing) = root.float("smoothing")? {
+            if !(smoothing >= 0.0 && smoothing.is_finite()) {
+                return Err(root.error(
+                    "smoothing",
+                    format!("must be finite and not negative, got {}", smoothing),
+                ));
+            }
+            config.smoothing = smoothing;
+        }
+        if let Some(brightness) = root.unit_float("brightness")? {
+            config.brightness = brightness;
+        }
+
+        if let Some(colors) = root.table("colors")? {
+            colors.check_keys(&["saturation", "value", "hue"])?;
+            if let Some(saturation) = colors.unit_float("saturation")? {
+                config.colors.saturation = saturation;
+            }
+            if let Some(value) = colors.unit_float("value")? {
+                config.colors.value = value;
+            }
+            if let Some(hue) = colors.finite_float("hue")? {
+                config.colors.hue = hue;
+            }
+        }
+
+        if let Some(monitor) = root.table("monitor")? {
+            monitor.check_keys(&["indices", "layout"])?;
+            if let Some(indices) = monitor.array("indices")? {
+                config.monitors.monitors = indices
+                    .iter()
+                    .map(|index| to_u32(index).map(|index| index as usize))
+                    .collect::<Option<Vec<usize>>>()
+                    .ok_or_else(|| {
+                        monitor.error("indices", "expected an array of monitor indices".to_string())
+                    })?;
+            }
+            if let Some(layout) = monitor.parsed::<MonitorLayout>("layout")? {
+                config.monitors.layout = layout;
+            }
+        }
+
+        if let Some(window) = root.table("window")? {
+            window.check_keys(&["fullscreen", "size", "frame_pacing"])?;
+            if let Some(fullscreen) = window.bool("fullscreen")? {
+                config.fullscreen = fullscreen;
+            }
+            if let Some(size) = window.array("size")? {
+                config.window_size = match size.iter().map(to_u32).collect::<Option<Vec<u32>>>() {
+                    Some(size) if size.len() == 2 && size[0] > 0 && size[1] > 0 => {
+                        (size[0], size[1])
+                    }
+                    _ => {
+                        return Err(
+                            window.error("size", "expected [width, height]".to_string())
+                        )
+                    }
+                };
+            }
+            if let Some(frame_pacing) = window.parsed::<FramePacing>("frame_pacing")? {
+                config.frame_pacing = frame_pacing;
+            }
+        }
+
+        if let Some(text) = root.table("text")? {
+            text.check_keys(&["font"])?;
+            if let Some(font) = text.string("font")? {
+                config.font = Some(PathBuf::from(font));
+            }
+        }
+
+        if let Some(qr) = root.table("qr")? {
+            qr.check_keys(&["url", "corner", "size", "visible", "hidden"])?;
+            let url = qr
+                .string("url")?
+                .ok_or_else(|| qr.error("url", "is required".to_string()))?;
+            let mut qr_code = QrCodeConfig::new(url);
+            if let Some(corner) = qr.parsed::<Corner>("corner")? {
+                qr_code.corner = corner;
+            }
+            if let Some(size) = qr.unit_float("size")? {
+                qr_code.size = size;
+            }
+            if let Some(visible) = qr.positive_float("visible")? {
+                qr_code.visible = visible;
+            }
+            if let Some(hidden) = qr.float("hidden")? {
+                if !(hidden >= 0.0) {
+                    return Err(qr.error("hidden", format!("must not be negative, got {}", hidden)));
+                }
+                qr_code.hidden = hidden;
+            }
+            config.qr_code = Some(qr_code);
+        }
+
+        if let Some(input) = root.table("input")? {
+            input.check_keys(&["source", "listen"])?;
+            if let Some(source) = input.parsed::<InputSource>("source")? {
+                config.input = Some(source);
+            }
+            if let Some(listen) = input.array("listen")? {
+                config.listeners = listen
+                    .iter()
+                    .map(|address| match address {
+                        toml::Value::String(address) => address
+                            .parse::<SocketAddr>()
+                            .map_err(|e| format!("invalid address {}: {}", address, e)),
+                        _ => Err("expected an array of addresses".to_string()),
+                    })
+                    .collect::<Result<Vec<SocketAddr>, String>>()
+                    .map_err(|e| input.error("listen", e))?;
+            }
+        }
+
+        *self = config;
+        Ok(())
+    }
+
+    /// The input sources to read from: the configured source and all listeners.
+    pub fn input_sources(&self) -> Vec<InputSource> {
+        self.input
+            .iter()
+            .cloned()
+            .chain(self.listeners.iter().cloned().map(InputSource::Network))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        config.update_from_str(text)?;
+        Ok(config)
+    }
+
+    fn error_key(text: &str) -> String {
+        parse(text).unwrap_err().key
+    }
+
+    #[test]
+    fn missing_keys_keep_defaults() {
+        let config = parse("ups = 60").unwrap();
+        assert_eq!(config.ups, 60.0);
+        assert_eq!(config.num_particles, Config::default().num_particles);
+        assert_eq!(config.logo, None);
+        assert_eq!(config.input, None);
+    }
+
+    #[test]
+    fn parses_all_sections() {
+        let config = parse(
+            r#"
+            variant = "SnowyCircle"
+            logo = "none"
+            particles = 100
+            smoothing = 0
+            brightness = 0.5
+
+            [colors]
+            hue = 0.25
+
+            [monitor]
+            indices = [0, 1]
+            layout = "extend"
+
+            [window]
+            fullscreen = false
+            size = [800, 600]
+            frame_pacing = "timer"
+
+            [qr]
+            url = "http://raveberry.local"
+            hidden = 0
+
+            [input]
+            source = "stdin"
+            listen = ["0.0.0.0:7700"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.variant, "SnowyCircle");
+        assert_eq!(config.logo, Some(LogoSource::Hidden));
+        assert_eq!(config.num_particles, 100);
+        assert_eq!(config.smoothing, 0.0);
+        assert_eq!(config.brightness, 0.5);
+        assert_eq!(config.colors.hue, 0.25);
+        assert_eq!(config.monitors.monitors, vec![0, 1]);
+        assert_eq!(config.monitors.layout, MonitorLayout::Extend);
+        assert!(!config.fullscreen);
+        assert_eq!(config.window_size, (800, 600));
+        assert_eq!(config.frame_pacing, FramePacing::Timer);
+        let qr_code = config.qr_code.as_ref().unwrap();
+        assert_eq!(qr_code.url, "http://raveberry.local");
+        assert_eq!(qr_code.hidden, 0.0);
+        assert_eq!(qr_code.visible, 20.0);
+        assert_eq!(
+            config.input_sources(),
+            vec![
+                InputSource::Stdin,
+                InputSource::Network("0.0.0.0:7700".parse().unwrap())
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_key_of_invalid_values() {
+        assert_eq!(error_key("ups = 0"), "ups");
+        assert_eq!(error_key("ups = inf"), "ups");
+        assert_eq!(error_key("particles = -1"), "particles");
+        assert_eq!(error_key("brightness = 2"), "brightness");
+        assert_eq!(error_key("[colors]\nsaturation = \"high\""), "colors.saturation");
+        assert_eq!(error_key("[window]\nsize = [800]"), "window.size");
+        assert_eq!(error_key("[monitor]\nlayout = \"stack\""), "monitor.layout");
+        assert_eq!(error_key("[qr]\ncorner = \"top_left\""), "qr.url");
+        assert_eq!(error_key("[input]\nlisten = [\"localhost\"]"), "input.listen");
+    }
+
+    #[test]
+    fn rejects_non_finite_hues() {
+        assert_eq!(error_key("[colors]\nhue = nan"), "colors.hue");
+        assert_eq!(error_key("[colors]\nhue = inf"), "colors.hue");
+        assert_eq!(parse("[colors]\nhue = -1.5").unwrap().colors.hue, -1.5);
+    }
+
+    #[test]
+    fn variant_paths_must_be_directories() {
+        let directory = std::env::temp_dir();
+        let config = parse(&format!("variant_paths = [{:?}]", directory)).unwrap();
+        assert_eq!(config.variant_paths, vec![directory]);
+        assert_eq!(
+            error_key("variant_paths = [\"/nonexistent/raveberry/visualizations\"]"),
+            "variant_paths"
+        );
+    }
+
+    #[test]
+    fn reports_unknown_keys() {
+        assert_eq!(error_key("fps = 60"), "fps");
+        assert_eq!(error_key("[window]\nvsync = true"), "window.vsync");
+        assert_eq!(error_key("window = 1"), "window");
+    }
+
+    #[test]
+    fn invalid_documents_change_nothing() {
+        assert_eq!(error_key("ups = "), "");
+        let mut config = Config::default();
+        assert!(config.update_from_str("ups = 60\nparticles = -1").is_err());
+        assert_eq!(config.ups, Config::default().ups);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::stats::PassTimes;
+use glium::draw_parameters::TimeElapsedQuery;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// results are usually available after one or two frames,
+// if they take longer, drop the oldest queries instead of piling them up
+const MAX_PENDING: usize = 4;
+
+/// One timer query for each pass of a single frame.
+pub struct PassQueries {
+    pub background: TimeElapsedQuery,
+    /// None if no particles are drawn, a query that is never used would never become ready
+    pub particles: Option<TimeElapsedQuery>,
+    pub foreground: TimeElapsedQuery,
+}
+
+impl PassQueries {
+    /// Fails if the context does not support timer queries.
+    pub fn new(
+        display: &glium::Display,
+        draws_particles: bool,
+    ) -> Result<PassQueries, glium::draw_parameters::QueryCreationError> {
+        Ok(PassQueries {
+            background: TimeElapsedQuery::new(display)?,
+            particles: if draws_particles {
+                Some(TimeElapsedQuery::new(display)?)
+            } else {
+                None
+            },
+            foreground: TimeElapsedQuery::new(display)?,
+        })
+    }
+
+    fn is_ready(&self) -> bool {
+        self.background.is_ready()
+            && self.particles.as_ref().map_or(true, |q| q.is_ready())
+            && self.foreground.is_ready()
+    }
+
+    fn get(self) -> PassTimes {
+        PassTimes {
+            background: Duration::from_nanos(self.background.get() as u64),
+            particles: Duration::from_nanos(self.particles.map_or(0, |q| q.get()) as u64),
+            foreground: Duration::from_nanos(self.foreground.get() as u64),
+            present: Duration::new(0, 0),
+        }
+    }
+}
+
+/// Keeps the queries of previous frames until the GPU has finished them,
+/// so reading the results never stalls the pipeline.
+pub struct GpuTimers {
+    pending: VecDeque<PassQueries>,
+}
+
+impl GpuTimers {
+    pub fn new() -> GpuTimers {
+        GpuTimers {
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, queries: PassQueries) {
+        if self.pending.len() >= MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(queries);
+    }
+
+    /// Returns the results of all queries that are finished, oldest first.
+    pub fn collect(&mut self) -> Vec<PassTimes> {
+        let mut results = Vec::new();
+        while self.pending.front().map_or(false, |q| q.is_ready()) {
+            results.push(self.pending.pop_front().unwrap().get());
+        }
+        results
+    }
+}
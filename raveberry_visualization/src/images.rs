@@ -0,0 +1,327 @@
+use glium::backend::Facade;
+use glium::texture::Texture2d;
+use glium::uniforms::{SamplerBehavior, SamplerWrapFunction, UniformType};
+use glium::CapabilitiesSource;
+use image::{ImageFormat, RgbaImage};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Seconds it takes to fade from the previous image of a slot to the new one.
+pub const TRANSITION_DURATION: f32 = 1.0;
+/// The texture size every OpenGL ES 3 implementation supports,
+/// assumed until the visualization reported the actual limit.
+pub const MIN_MAX_TEXTURE_SIZE: u32 = 2048;
+
+/// Decodes a PNG or JPEG image, scaling it down to fit into a texture of the given size.
+pub fn decode(data: &[u8], max_size: u32) -> Result<RgbaImage, String> {
+    let format = image_format(data)?;
+    let image = image::load_from_memory_with_format(data, format)
+        .map_err(|e| format!("Could not decode image: {}", e))?;
+    Ok(fit(image.to_rgba8(), max_size))
+}
+
+/// Scales the image down until neither side is longer than `max_size`, keeping its aspect ratio.
+pub fn fit(image: RgbaImage, max_size: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    if width <= max_size && height <= max_size {
+        return image;
+    }
+    let scale = max_size as f32 / width.max(height) as f32;
+    let size = |side: u32| ((side as f32 * scale).round() as u32).max(1).min(max_size);
+    image::imageops::resize(
+        &image,
+        size(width),
+        size(height),
+        image::imageops::FilterType::Triangle,
+    )
+}
+
+/// The longest side of a texture the context supports.
+pub fn max_texture_size<F: Facade>(facade: &F) -> u32 {
+    facade
+        .get_context()
+        .get_capabilities()
+        .max_texture_size
+        .max(1) as u32
+}
+
+/// Uploads an image into a texture, scaling it down if it is too large for the context.
+pub fn upload<F: Facade>(facade: &F, image: &RgbaImage) -> Result<Texture2d, String> {
+    let max_size = max_texture_size(facade);
+    let fitted;
+    let image = if image.width() > max_size || image.height() > max_size {
+        fitted = fit(image.clone(), max_size);
+        &fitted
+    } else {
+        image
+    };
+    let raw = glium::texture::RawImage2d::from_raw_rgba_reversed(image, image.dimensions());
+    // don't use an sRGB texture because the shader is already configured to output sRGB
+    Texture2d::new(facade, raw).map_err(|e| format!("Could not upload image: {}", e))
+}
+
+/// Checks that the data looks like a PNG or JPEG image, without decoding it.
+pub fn image_format(data: &[u8]) -> Result<ImageFormat, String> {
+    match image::guess_format(data) {
+        Ok(format @ ImageFormat::Png) | Ok(format @ ImageFormat::Jpeg) => Ok(format),
+        Ok(format) => Err(format!("Unsupported image format {:?}, expected PNG or JPEG", format)),
+        Err(_) => Err("Unknown image format, expected PNG or JPEG".to_string()),
+    }
+}
+
+/// Decodes images in the background, one at a time.
+/// Requests for a slot that are still pending when a newer one arrives are dropped,
+/// so a burst of images only decodes the last one.
+pub struct ImageDecoder {
+    queue: Arc<Mutex<DecodeQueue>>,
+    // receives the decoded image of a slot together with its request number
+    sink: Arc<dyn Fn(&str, usize, RgbaImage) + Send + Sync>,
+}
+
+struct DecodeRequest {
+    request: usize,
+    data: Vec<u8>,
+    max_size: u32,
+}
+
+#[derive(Default)]
+struct DecodeQueue {
+    pending: HashMap<String, DecodeRequest>,
+    worker_running: bool,
+}
+
+impl DecodeQueue {
+    // Returns whether a worker needs to be started for the request.
+    fn push(&mut self, slot: &str, request: DecodeRequest) -> bool {
+        self.pending.insert(slot.to_string(), request);
+        !std::mem::replace(&mut self.worker_running, true)
+    }
+
+    // Takes the next request, marking the worker as stopped once there is none left.
+    fn pop(&mut self) -> Option<(String, DecodeRequest)> {
+        let slot = self.pending.keys().next().cloned();
+        let next = slot.and_then(|slot| self.pending.remove_entry(&slot));
+        if next.is_none() {
+            self.worker_running = false;
+        }
+        next
+    }
+}
+
+impl ImageDecoder {
+    pub fn new<S: Fn(&str, usize, RgbaImage) + Send + Sync + 'static>(sink: S) -> ImageDecoder {
+        ImageDecoder {
+            queue: Arc::new(Mutex::new(DecodeQueue::default())),
+            sink: Arc::new(sink),
+        }
+    }
+
+    /// Queues the data of a slot for decoding, replacing a pending request of the same slot.
+    pub fn decode(&self, slot: &str, request: usize, data: Vec<u8>, max_size: u32) {
+        let request = DecodeRequest {
+            request: request,
+            data: data,
+            max_size: max_size,
+        };
+        if !self.queue.lock().unwrap().push(slot, request) {
+            return;
+        }
+        let queue = Arc::clone(&self.queue);
+        let sink = Arc::clone(&self.sink);
+        thread::spawn(move || loop {
+            let next = queue.lock().unwrap().pop();
+            let (slot, request) = match next {
+                Some(next) => next,
+                None => return,
+            };
+            match decode(&request.data, request.max_size) {
+                Ok(image) => sink(&slot, request.request, image),
+                Err(e) => eprintln!("{}: {}", slot, e),
+            }
+        });
+    }
+
+    /// Drops a pending request of the slot.
+    pub fn cancel(&self, slot: &str) {
+        self.queue.lock().unwrap().pending.remove(slot);
+    }
+}
+
+/// The uniforms a shader accesses an image slot through.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlotUniform {
+    Current,
+    Previous,
+    Transition,
+}
+
+impl SlotUniform {
+    /// The type the uniform has to be declared with.
+    pub fn ty(self) -> UniformType {
+        match self {
+            SlotUniform::Current | SlotUniform::Previous => UniformType::Sampler2d,
+            SlotUniform::Transition => UniformType::Float,
+        }
+    }
+}
+
+/// The slot a uniform declared by a shader refers to, if it is one of the uniforms
+/// of the slots the variant lists in its manifest.
+///
+/// A slot named `cover` is accessed through the uniforms
+/// ```glsl
+/// uniform sampler2D cover;
+/// // the image that was shown before the current one
+/// uniform sampler2D cover_previous;
+/// // rises from 0 to 1 after the image changed
+/// uniform float cover_transition;
+/// ```
+/// Slots that were never set are transparent.
+pub fn slot_of<'a>(name: &str, slots: &'a [String]) -> Option<(&'a str, SlotUniform)> {
+    slots.iter().find_map(|slot| {
+        let uniform = match name.strip_prefix(slot.as_str())? {
+            "" => SlotUniform::Current,
+            "_previous" => SlotUniform::Previous,
+            "_transition" => SlotUniform::Transition,
+            _ => return None,
+        };
+        Some((slot.as_str(), uniform))
+    })
+}
+
+struct SlotTextures {
+    current: Texture2d,
+    previous: Texture2d,
+}
+
+/// The textures of all image slots of an output.
+pub struct ImageTextures {
+    // stands in for slots that were not set yet
+    empty: Texture2d,
+    slots: HashMap<String, SlotTextures>,
+}
+
+fn empty_texture<F: Facade>(facade: &F) -> Texture2d {
+    let image = glium::texture::RawImage2d::from_raw_rgba(vec![0u8; 4], (1, 1));
+    Texture2d::new(facade, image).unwrap()
+}
+
+impl ImageTextures {
+    pub fn new<F: Facade>(facade: &F) -> ImageTextures {
+        ImageTextures {
+            empty: empty_texture(facade),
+            slots: HashMap::new(),
+        }
+    }
+
+    /// Uploads the new image of a slot, keeping the current one as the previous image.
+    /// A slot without image becomes transparent. If the upload fails, the slot is left unchanged.
+    pub fn set<F: Facade>(&mut self, facade: &F, slot: &str, image: Option<&RgbaImage>) {
+        let texture = match image.map(|image| upload(facade, image)) {
+            Some(Ok(texture)) => texture,
+            Some(Err(e)) => {
+                eprintln!("{}: {}", slot, e);
+                return;
+            }
+            None => empty_texture(facade),
+        };
+        let previous = match self.slots.remove(slot) {
+            Some(textures) => textures.current,
+            None => empty_texture(facade),
+        };
+        self.slots.insert(
+            slot.to_string(),
+            SlotTextures {
+                current: texture,
+                previous: previous,
+            },
+        );
+    }
+
+    /// The current or previous texture of a slot.
+    pub fn texture(&self, slot: &str, previous: bool) -> (&Texture2d, SamplerBehavior) {
+        let texture = self.slots.get(slot).map(|t| {
+            if previous {
+                &t.previous
+            } else {
+                &t.current
+            }
+        });
+        let behavior = SamplerBehavior {
+            // transparent outside of the image
+            wrap_function: (
+                SamplerWrapFunction::BorderClamp,
+                SamplerWrapFunction::BorderClamp,
+                SamplerWrapFunction::BorderClamp,
+            ),
+            ..Default::default()
+        };
+        (texture.unwrap_or(&self.empty), behavior)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(request: usize) -> DecodeRequest {
+        DecodeRequest {
+            request: request,
+            data: Vec::new(),
+            max_size: MIN_MAX_TEXTURE_SIZE,
+        }
+    }
+
+    #[test]
+    fn fit_keeps_small_images() {
+        let image = fit(RgbaImage::new(300, 200), 512);
+        assert_eq!(image.dimensions(), (300, 200));
+    }
+
+    #[test]
+    fn fit_downscales_keeping_aspect_ratio() {
+        assert_eq!(fit(RgbaImage::new(4000, 1000), 200).dimensions(), (200, 50));
+        assert_eq!(fit(RgbaImage::new(1000, 4000), 200).dimensions(), (50, 200));
+        assert_eq!(fit(RgbaImage::new(10000, 1), 100).dimensions(), (100, 1));
+    }
+
+    #[test]
+    fn slot_uniforms() {
+        let slots = vec!["cover".to_string(), "cover_art".to_string()];
+        assert_eq!(slot_of("cover", &slots), Some(("cover", SlotUniform::Current)));
+        assert_eq!(
+            slot_of("cover_previous", &slots),
+            Some(("cover", SlotUniform::Previous))
+        );
+        assert_eq!(
+            slot_of("cover_art_transition", &slots),
+            Some(("cover_art", SlotUniform::Transition))
+        );
+        assert_eq!(slot_of("cover_color", &slots), None);
+        assert_eq!(slot_of("speed_previous", &slots), None);
+        assert_eq!(slot_of("cover", &[]), None);
+    }
+
+    #[test]
+    fn decode_queue_starts_one_worker() {
+        let mut queue = DecodeQueue::default();
+        assert!(queue.push("cover", request(1)));
+        assert!(!queue.push("artist", request(2)));
+        assert!(queue.pop().is_some());
+        assert!(queue.pop().is_some());
+        assert!(queue.pop().is_none());
+        assert!(queue.push("cover", request(3)));
+    }
+
+    #[test]
+    fn decode_queue_drops_superseded_requests() {
+        let mut queue = DecodeQueue::default();
+        queue.push("cover", request(1));
+        queue.push("cover", request(2));
+        let (slot, next) = queue.pop().unwrap();
+        assert_eq!(slot, "cover");
+        assert_eq!(next.request, 2);
+        assert!(queue.pop().is_none());
+    }
+}
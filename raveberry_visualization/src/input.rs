@@ -0,0 +1,256 @@
+use crate::BARS;
+use std::fs;
+use std::io::{self, BufRead};
+use std::net::{SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A single update of the visualization's parameters.
+#[derive(Clone)]
+pub struct Frame {
+    pub alarm_factor: f32,
+    pub spectrum: [f32; BARS as usize],
+}
+
+/// Where spectrum frames come from when they are not passed in through `set_parameters`.
+///
+/// All sources except the synthetic one use a text format with one frame per line:
+/// `BARS` values separated by whitespace or commas, optionally preceded by the alarm factor.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputSource {
+    /// a generated spectrum that only depends on the time
+    Synthetic,
+    Stdin,
+    /// played back in a loop at the given ups
+    File(PathBuf),
+    /// one frame per UDP datagram
+    Network(SocketAddr),
+}
+
+impl FromStr for InputSource {
+    type Err = String;
+
+    /// Parses `synthetic`, `stdin`, `file:<path>` or `udp:<host>:<port>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "synthetic" {
+            return Ok(InputSource::Synthetic);
+        }
+        if s == "stdin" {
+            return Ok(InputSource::Stdin);
+        }
+        if let Some(path) = s.strip_prefix("file:") {
+            return Ok(InputSource::File(PathBuf::from(path)));
+        }
+        if let Some(address) = s.strip_prefix("udp:") {
+            return address
+                .parse::<SocketAddr>()
+                .map(InputSource::Network)
+                .map_err(|e| format!("Invalid address {}: {}", address, e));
+        }
+        Err(format!("Unknown input source: {}", s))
+    }
+}
+
+/// A spectrum that only depends on the time, so runs are comparable.
+pub fn synthetic_spectrum(seconds_elapsed: f32) -> [f32; BARS as usize] {
+    let mut spectrum = [0.0; BARS as usize];
+    for (i, val) in spectrum.iter_mut().enumerate() {
+        *val = 0.8
+            * 0.5
+            * (1.0 + (4.0 * seconds_elapsed).sin())
+            * 0.5
+            * (1.0 + (-5.0 * seconds_elapsed + 2.0 * i as f32).sin())
+    }
+    spectrum
+}
+
+pub fn parse_frame(line: &str) -> Result<Frame, String> {
+    let values = line
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse::<f32>().map_err(|e| format!("Invalid value {}: {}", v, e)))
+        .collect::<Result<Vec<f32>, String>>()?;
+    let (alarm_factor, values) = if values.len() == BARS as usize + 1 {
+        (values[0], &values[1..])
+    } else if values.len() == BARS as usize {
+        (-1.0, &values[..])
+    } else {
+        return Err(format!(
+            "Expected {} or {} values, got {}",
+            BARS,
+            BARS + 1,
+            values.len()
+        ));
+    };
+    let mut spectrum = [0.0; BARS as usize];
+    spectrum.copy_from_slice(values);
+    Ok(Frame {
+        alarm_factor: alarm_factor,
+        spectrum: spectrum,
+    })
+}
+
+/// Reads frames from the source on a new thread and passes them to `sink`
+/// until it returns false. `sink` is called with None when a source is idle,
+/// so it can still be stopped. Sources are opened before returning, so errors are reported directly.
+pub fn spawn<F>(source: InputSource, ups: f32, mut sink: F) -> io::Result<thread::JoinHandle<()>>
+where
+    F: FnMut(Option<Frame>) -> bool + Send + 'static,
+{
+    let interval = Duration::from_secs_f32(1.0 / ups);
+    match source {
+        InputSource::Synthetic => Ok(thread::spawn(move || {
+            let start = Instant::now();
+            loop {
+                let frame = Frame {
+                    alarm_factor: -1.0,
+                    spectrum: synthetic_spectrum(start.elapsed().as_secs_f32()),
+                };
+                if !sink(Some(frame)) {
+                    return;
+                }
+                thread::sleep(interval);
+            }
+        })),
+        InputSource::Stdin => Ok(thread::spawn(move || {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => return,
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match parse_frame(&line) {
+                    Ok(frame) => {
+                        if !sink(Some(frame)) {
+                            return;
+                        }
+                    }
+                    Err(e) => eprintln!("Skipping input line: {}", e),
+                }
+            }
+        })),
+        InputSource::File(path) => {
+            let frames = fs::read_to_string(&path)?
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(parse_frame)
+                .collect::<Result<Vec<Frame>, String>>()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            if frames.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} contains no frames", path.display()),
+                ));
+            }
+            Ok(thread::spawn(move || {
+                for frame in frames.iter().cycle() {
+                    if !sink(Some(frame.clone())) {
+                        return;
+                    }
+                    thread::sleep(interval);
+                }
+            }))
+        }
+        InputSource::Network(address) => {
+            let socket = UdpSocket::bind(address)?;
+            // wake up regularly to notice when the sink is no longer interested
+            socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+            Ok(thread::spawn(move || {
+                let mut buffer = [0; 65536];
+                loop {
+                    let frame = match socket.recv(&mut buffer) {
+                        Ok(length) => match std::str::from_utf8(&buffer[..length])
+                            .map_err(|e| e.to_string())
+                            .and_then(parse_frame)
+                        {
+                            Ok(frame) => Some(frame),
+                            Err(e) => {
+                                eprintln!("Skipping datagram: {}", e);
+                                None
+                            }
+                        },
+                        Err(e)
+                            if e.kind() == io::ErrorKind::WouldBlock
+                                || e.kind() == io::ErrorKind::TimedOut =>
+                        {
+                            None
+                        }
+                        Err(e) => {
+                            eprintln!("Could not receive input: {}", e);
+                            return;
+                        }
+                    };
+                    if !sink(frame) {
+                        return;
+                    }
+                }
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(values: &[f32], separator: &str) -> String {
+        values
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    #[test]
+    fn parses_sources() {
+        assert_eq!("synthetic".parse(), Ok(InputSource::Synthetic));
+        assert_eq!("stdin".parse(), Ok(InputSource::Stdin));
+        assert_eq!(
+            "file:/tmp/spectrum.txt".parse(),
+            Ok(InputSource::File(PathBuf::from("/tmp/spectrum.txt")))
+        );
+        assert_eq!(
+            "udp:127.0.0.1:5000".parse(),
+            Ok(InputSource::Network("127.0.0.1:5000".parse().unwrap()))
+        );
+        assert!("udp:localhost".parse::<InputSource>().is_err());
+        assert!("microphone".parse::<InputSource>().is_err());
+    }
+
+    #[test]
+    fn parses_frame_without_alarm_factor() {
+        let values = (0..BARS).map(|i| i as f32 / BARS as f32).collect::<Vec<_>>();
+        let frame = parse_frame(&line(&values, " ")).unwrap();
+        assert_eq!(frame.alarm_factor, -1.0);
+        assert_eq!(&frame.spectrum[..], &values[..]);
+    }
+
+    #[test]
+    fn parses_frame_with_alarm_factor() {
+        let mut values = vec![0.5; BARS as usize + 1];
+        values[0] = 0.25;
+        let frame = parse_frame(&line(&values, ", ")).unwrap();
+        assert_eq!(frame.alarm_factor, 0.25);
+        assert!(frame.spectrum.iter().all(|v| *v == 0.5));
+    }
+
+    #[test]
+    fn rejects_invalid_frames() {
+        assert!(parse_frame(&line(&[0.5; 10], " ")).is_err());
+        assert!(parse_frame(&line(&vec![0.5; BARS as usize + 2], " ")).is_err());
+        let mut values = line(&vec![0.5; BARS as usize - 1], " ");
+        values.push_str(" loud");
+        assert!(parse_frame(&values).is_err());
+    }
+
+    #[test]
+    fn synthetic_spectrum_is_deterministic() {
+        assert_eq!(synthetic_spectrum(1.5)[..], synthetic_spectrum(1.5)[..]);
+        assert!(synthetic_spectrum(1.5).iter().all(|v| *v >= 0.0 && *v <= 0.8));
+    }
+}
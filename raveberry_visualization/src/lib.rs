@@ -1,19 +1,61 @@
 #[macro_use]
 extern crate glium;
 
+mod adaptive;
+mod assets;
+mod banner;
+pub mod config;
+mod gpu_timer;
+mod images;
+pub mod input;
+pub mod logo;
+pub mod monitor;
+mod output;
+mod overlay;
+mod pacing;
+pub mod qr;
+mod shaders;
+mod simulation;
+pub mod stats;
+mod text;
+mod uniforms;
+pub mod validation;
+pub mod variants;
+
 use crate::glium::glutin::platform::run_return::EventLoopExtRunReturn;
-use glium::{glutin, Surface};
+use adaptive::AdaptiveQuality;
+use banner::{Banner, BannerQueue, BannerStyle};
+use config::{Colors, Config};
+use glium::glutin;
+use input::InputSource;
 use lazy_static::lazy_static;
+use logo::Logo;
+use monitor::{Monitor, MonitorLayout};
+use output::{FrameParameters, Output, PngSequence, RenderScale, UpscaleFilter};
+use pacing::FramePacer;
+use qr::{Corner, QrOverlay};
+pub use pacing::FramePacing;
 use palette::{Hsv, IntoColor, Srgb};
+use simulation::Simulation;
+use stats::{Stats, StatsCollector};
+use text::{ShownText, TextDraw, TextFrame, TextOverlay};
+use uniforms::UserUniform;
+use validation::VariantReport;
+use variants::Variant;
 use pyo3::prelude::*;
+use std::collections::HashMap;
 use std::fs;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Mutex;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
 use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 pub const BARS: u32 = 256;
 const PARTICLE_SPAWN_Z: f32 = 2.0;
+/// More particles would only allocate large buffers without being visible.
+pub const MAX_PARTICLES: u32 = 100_000;
 
 #[derive(Copy, Clone)]
 struct Vertex {
@@ -29,480 +71,1646 @@ struct Particle {
 }
 implement_vertex!(Particle, translation, start_z, speed);
 
-static mut ACTIVE: AtomicBool = AtomicBool::new(false);
-static mut SHOULD_EXIT: AtomicBool = AtomicBool::new(false);
 lazy_static! {
+    // only used as the default for newly created controllers
     static ref MODULE_ROOT: Mutex<String> = Mutex::new("./".to_string());
-    static ref AVG_FPS: Mutex<f32> = Mutex::new(-1.0);
-    static ref ALARM_FACTOR: Mutex<f32> = Mutex::new(-1.0);
-    static ref CURRENT_FRAME: Mutex<[f32; BARS as usize]> = Mutex::new([0.0; BARS as usize]);
+}
+
+// State shared between a controller and the render thread it spawned.
+// Every controller owns its own instance, so multiple visualizations can run side by side.
+struct SharedState {
+    active: Mutex<bool>,
+    active_changed: Condvar,
+    should_exit: AtomicBool,
+    fullscreen: AtomicBool,
+    // logical size used in windowed mode
+    window_size: Mutex<(u32, u32)>,
+    render_scale: Mutex<RenderScale>,
+    // None if adaptive quality is disabled
+    adaptive_target_fps: Mutex<Option<f32>>,
+    quality_level: AtomicUsize,
+    gpu_timing: AtomicBool,
+    avg_fps: Mutex<f32>,
+    stats: Mutex<Option<Stats>>,
+    // when set_parameters was last called
+    last_input: Mutex<Option<Instant>>,
+    // incremented whenever the input source changes, so the reader of the old one stops
+    input_generation: AtomicUsize,
+    // the settings passed to start, which can be changed while running
+    ups: Mutex<f32>,
+    num_particles: Mutex<u32>,
+    fps_measure_window: Mutex<f32>,
+    // sigma of the gauss filter applied to the spectrum
+    smoothing: Mutex<f32>,
+    brightness: Mutex<f32>,
+    colors: Mutex<Colors>,
+    logo: Mutex<Logo>,
+    // set when the logo was replaced, so the running visualization uploads it
+    logo_changed: AtomicBool,
+    user_uniforms: Mutex<HashMap<String, UserUniform>>,
+    // the decoded image of every slot, None if it was cleared,
+    // together with the number of the request that set it
+    images: Mutex<HashMap<String, (usize, Option<Arc<image::RgbaImage>>)>>,
+    image_requests: AtomicUsize,
+    // the longest side of a texture the running visualization supports, images are decoded to fit
+    max_texture_size: AtomicU32,
+    // text overlays by name, None once removed, with the number of the request that set them
+    texts: Mutex<HashMap<String, (usize, Option<Arc<TextOverlay>>)>>,
+    text_requests: AtomicUsize,
+    // None to use one of the common system fonts
+    font: Mutex<Option<ab_glyph::FontArc>>,
+    // set when the font was replaced, so the running visualization switches to it
+    font_changed: AtomicBool,
+    qr_code: Mutex<Option<Arc<QrOverlay>>>,
+    // set when the QR code was replaced, so the running visualization uploads it
+    qr_code_changed: AtomicBool,
+    // banners passed to notify that the render thread did not queue yet
+    banners: Mutex<Vec<Banner>>,
+    // the user uniforms declared by the running variant, with their type
+    declared_uniforms: Mutex<Vec<(String, glium::uniforms::UniformType)>>,
+    // the image slots of the running variant
+    declared_image_slots: Mutex<Vec<String>>,
+    alarm_factor: Mutex<f32>,
+    current_frame: Mutex<[f32; BARS as usize]>,
+}
+
+impl SharedState {
+    fn new() -> SharedState {
+        SharedState {
+            active: Mutex::new(false),
+            active_changed: Condvar::new(),
+            should_exit: AtomicBool::new(false),
+            fullscreen: AtomicBool::new(true),
+            window_size: Mutex::new((1280, 720)),
+            render_scale: Mutex::new(RenderScale::default()),
+            adaptive_target_fps: Mutex::new(None),
+            quality_level: AtomicUsize::new(0),
+            gpu_timing: AtomicBool::new(false),
+            avg_fps: Mutex::new(-1.0),
+            stats: Mutex::new(None),
+            last_input: Mutex::new(None),
+            input_generation: AtomicUsize::new(0),
+            ups: Mutex::new(Config::default().ups),
+            num_particles: Mutex::new(Config::default().num_particles),
+            fps_measure_window: Mutex::new(Config::default().fps_measure_window),
+            smoothing: Mutex::new(Config::default().smoothing),
+            brightness: Mutex::new(1.0),
+            user_uniforms: Mutex::new(HashMap::new()),
+            images: Mutex::new(HashMap::new()),
+            image_requests: AtomicUsize::new(0),
+            max_texture_size: AtomicU32::new(images::MIN_MAX_TEXTURE_SIZE),
+            texts: Mutex::new(HashMap::new()),
+            text_requests: AtomicUsize::new(0),
+            font: Mutex::new(None),
+            font_changed: AtomicBool::new(false),
+            qr_code: Mutex::new(None),
+            qr_code_changed: AtomicBool::new(false),
+            banners: Mutex::new(Vec::new()),
+            declared_uniforms: Mutex::new(Vec::new()),
+            declared_image_slots: Mutex::new(Vec::new()),
+            colors: Mutex::new(Colors::default()),
+            logo: Mutex::new(Logo::Default),
+            logo_changed: AtomicBool::new(false),
+            alarm_factor: Mutex::new(-1.0),
+            current_frame: Mutex::new([0.0; BARS as usize]),
+        }
+    }
+
+    fn set_input(&self, alarm_factor: f32, current_frame: [f32; BARS as usize]) {
+        *self.last_input.lock().unwrap() = Some(Instant::now());
+        *self.alarm_factor.lock().unwrap() = alarm_factor;
+        *self.current_frame.lock().unwrap() = current_frame;
+    }
+
+    fn set_logo(&self, logo: Logo) {
+        *self.logo.lock().unwrap() = logo;
+        self.logo_changed.store(true, Ordering::Relaxed);
+    }
+
+    // Stores the image of a slot unless a later request already replaced it.
+    fn set_image(&self, slot: &str, request: usize, image: Option<image::RgbaImage>) {
+        let mut images = self.images.lock().unwrap();
+        if images.get(slot).map_or(true, |(r, _)| *r < request) {
+            images.insert(slot.to_string(), (request, image.map(Arc::new)));
+        }
+    }
+
+    fn set_text(&self, name: &str, text: Option<TextOverlay>) {
+        let request = self.text_requests.fetch_add(1, Ordering::SeqCst) + 1;
+        self.texts
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), (request, text.map(Arc::new)));
+    }
+
+    fn set_font(&self, font: ab_glyph::FontArc) {
+        *self.font.lock().unwrap() = Some(font);
+        self.font_changed.store(true, Ordering::Relaxed);
+    }
+
+    fn set_qr_code(&self, qr_code: Option<QrOverlay>) {
+        *self.qr_code.lock().unwrap() = qr_code.map(Arc::new);
+        self.qr_code_changed.store(true, Ordering::Relaxed);
+    }
+
+    fn set_active(&self, active: bool) {
+        *self.active.lock().unwrap() = active;
+        self.active_changed.notify_all();
+    }
+
+    // Blocks until the render thread is inactive or the timeout passed.
+    // Returns whether the render thread is inactive.
+    fn wait_inactive(&self, timeout: Option<Duration>) -> bool {
+        let active = self.active.lock().unwrap();
+        match timeout {
+            Some(timeout) => {
+                let (active, _) = self
+                    .active_changed
+                    .wait_timeout_while(active, timeout, |active| *active)
+                    .unwrap();
+                !*active
+            }
+            None => {
+                let active = self
+                    .active_changed
+                    .wait_while(active, |active| *active)
+                    .unwrap();
+                !*active
+            }
+        }
+    }
+}
+
+// Marks the render thread as inactive once it is dropped.
+// This way waiting callers are woken up even if the render thread panics.
+struct ActiveGuard(Arc<SharedState>);
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        self.0.set_active(false);
+    }
 }
 
 #[pyclass]
-pub struct Controller {}
+pub struct Controller {
+    module_root: String,
+    state: Arc<SharedState>,
+    render_thread: Mutex<Option<thread::JoinHandle<()>>>,
+    // provides the defaults for `start` as well as monitors and frame pacing,
+    // settings that can change at runtime live in the shared state
+    config: Mutex<Config>,
+    png_sequence: Mutex<Option<PngSequence>>,
+    benchmark: AtomicBool,
+    image_decoder: images::ImageDecoder,
+}
 
 #[pymethods]
 impl Controller {
     #[new]
     pub fn new() -> Self {
-        Controller {}
+        let state = Arc::new(SharedState::new());
+        let decoded = Arc::clone(&state);
+        Controller {
+            module_root: MODULE_ROOT.lock().unwrap().clone(),
+            state: state,
+            render_thread: Mutex::new(None),
+            config: Mutex::new(Config::default()),
+            png_sequence: Mutex::new(None),
+            benchmark: AtomicBool::new(false),
+            image_decoder: images::ImageDecoder::new(move |slot, request, image| {
+                decoded.set_image(slot, request, Some(image))
+            }),
+        }
     }
 
-    pub fn start(&self, variant: &str, ups: f32, num_particles: u32, fps_measure_window: f32) {
-        let variant = variant.to_string();
+    /// Starts the visualization. Arguments that are not given are taken from the loaded config.
+    /// The logo is the one last passed to `set_logo`, `set_logo_rgba` or `hide_logo`,
+    /// which can be called before starting as well as while running.
+    /// Blocks until the window opened and raises `ValueError` for invalid arguments
+    /// and `RuntimeError` if the visualization could not be started,
+    /// e.g. because it is already running or its shaders failed to compile.
+    #[args(
+        variant = "None",
+        ups = "None",
+        num_particles = "None",
+        fps_measure_window = "None"
+    )]
+    pub fn start(
+        &self,
+        py: Python,
+        variant: Option<&str>,
+        ups: Option<f32>,
+        num_particles: Option<u32>,
+        fps_measure_window: Option<f32>,
+    ) -> PyResult<()> {
+        // the shaders compile without the GIL so other python threads keep running
+        py.allow_threads(|| {
+            self.start_visualization(variant, ups, num_particles, fps_measure_window)
+        })
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidInput => pyo3::exceptions::PyValueError::new_err(e.to_string()),
+            _ => pyo3::exceptions::PyRuntimeError::new_err(e.to_string()),
+        })
+    }
 
-        if self.get_variants().iter().all(|s| s != &variant) {
-            eprintln!("Unknown variant given: {}", variant);
-            return;
+    /// Requests the visualization to exit and blocks until its window was destroyed.
+    /// Waits indefinitely if no timeout (in seconds) or an infinite one is given.
+    /// Returns whether the render thread exited in time.
+    #[args(timeout = "None")]
+    pub fn stop(&self, py: Python, timeout: Option<f32>) -> PyResult<bool> {
+        let timeout = timeout_duration(timeout).map_err(pyo3::exceptions::PyValueError::new_err)?;
+        self.request_stop();
+        // the render thread does not need the GIL, but other python threads might
+        Ok(py.allow_threads(|| self.join(timeout)))
+    }
+
+    /// Blocks until the visualization exited on its own, e.g. because its window was closed.
+    /// Returns whether the render thread exited before the timeout (in seconds) passed.
+    /// An infinite timeout waits indefinitely.
+    #[args(timeout = "None")]
+    pub fn wait(&self, py: Python, timeout: Option<f32>) -> PyResult<bool> {
+        let timeout = timeout_duration(timeout).map_err(pyo3::exceptions::PyValueError::new_err)?;
+        Ok(py.allow_threads(|| self.join(timeout)))
+    }
+
+    pub fn is_active(&self) -> bool {
+        *self.state.active.lock().unwrap()
+    }
+
+    pub fn __enter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    pub fn __exit__(
+        &self,
+        py: Python,
+        _exc_type: &PyAny,
+        _exc_value: &PyAny,
+        _traceback: &PyAny,
+    ) -> bool {
+        self.request_stop();
+        py.allow_threads(|| self.join(None));
+        // do not suppress exceptions raised inside the with block
+        false
+    }
+
+    /// Lists the variants that contain all shaders, sorted by name.
+    /// Variants from the paths given in the config take precedence over user installed ones
+    /// in `$XDG_DATA_HOME/raveberry/visualizations`, which take precedence over the bundled ones.
+    pub fn get_variants(&self) -> Vec<Variant> {
+        self.variants()
+    }
+
+    /// Sets the directories searched for variants before the user and package directories.
+    pub fn set_variant_paths(&self, paths: Vec<String>) {
+        self.config.lock().unwrap().variant_paths = paths.into_iter().map(PathBuf::from).collect();
+    }
+
+    /// Lists all directories searched for variants, in order of precedence.
+    pub fn get_variant_paths(&self) -> Vec<String> {
+        self.variant_paths()
+            .into_iter()
+            .map(|(path, _)| path.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    /// Compiles the shaders of every variant in a headless context without opening a window
+    /// and reports which of them can be used, including the GLSL logs of failed ones.
+    pub fn validate_variants(&self, py: Python) -> PyResult<Vec<VariantReport>> {
+        py.allow_threads(|| self.validate_all())
+            .map_err(pyo3::exceptions::PyRuntimeError::new_err)
+    }
+
+    /// Lists the monitors that can be passed to `select_monitors`.
+    pub fn get_monitors(&self) -> Vec<Monitor> {
+        let event_loop: glutin::event_loop::EventLoop<()> =
+            glutin::platform::unix::EventLoopExtUnix::new_any_thread();
+        monitor::list(&event_loop)
+    }
+
+    /// Selects the monitors (by index) used by the next call to `start`.
+    /// With the "mirror" layout, every monitor shows its own copy of the visualization,
+    /// with "extend" a single visualization spans all of them.
+    /// When mirroring with vsync, only the first selected monitor is synchronized.
+    /// An empty list selects the primary monitor.
+    #[args(layout = "\"mirror\"")]
+    pub fn select_monitors(&self, monitors: Vec<usize>, layout: &str) -> PyResult<()> {
+        let layout = layout
+            .parse::<MonitorLayout>()
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+        let mut config = self.config.lock().unwrap();
+        config.monitors.monitors = monitors;
+        config.monitors.layout = layout;
+        Ok(())
+    }
+
+    /// Selects how frames are paced for the next call to `start`:
+    /// "vsync" renders at the refresh rate of the display,
+    /// "timer" renders at the given ups without vsync,
+    /// "uncapped" renders as fast as possible and
+    /// "adaptive_vsync" is vsync that tears instead of stalling when a frame is late.
+    pub fn set_frame_pacing(&self, pacing: &str) -> PyResult<()> {
+        self.config.lock().unwrap().frame_pacing = pacing
+            .parse::<FramePacing>()
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+        Ok(())
+    }
+
+    /// Renders the next `start` into numbered png files in the given directory instead of the screen.
+    /// Every frame advances the time by 1/ups, independent of how long it took to render.
+    /// The visualization exits after the given number of frames, if any.
+    /// Pass None to render to the screen again.
+    #[args(frames = "None")]
+    pub fn set_png_output(&self, directory: Option<String>, frames: Option<u32>) {
+        *self.png_sequence.lock().unwrap() = directory.map(|directory| PngSequence {
+            directory: PathBuf::from(directory),
+            frames: frames,
+        });
+    }
+
+    /// Changes the updates per second. Applies to the running visualization
+    /// from the next frame on and is used by later calls to `start`.
+    pub fn set_ups(&self, ups: f32) -> PyResult<()> {
+        check_ups(ups).map_err(pyo3::exceptions::PyValueError::new_err)?;
+        self.config.lock().unwrap().ups = ups;
+        *self.state.ups.lock().unwrap() = ups;
+        Ok(())
+    }
+
+    /// Changes the number of particles, respawning all of them. Takes effect on the next frame.
+    pub fn set_num_particles(&self, num_particles: u32) -> PyResult<()> {
+        if num_particles > MAX_PARTICLES {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Number of particles must be at most {}, got {}",
+                MAX_PARTICLES, num_particles
+            )));
+        }
+        self.config.lock().unwrap().num_particles = num_particles;
+        *self.state.num_particles.lock().unwrap() = num_particles;
+        Ok(())
+    }
+
+    /// Changes the length of the fps measurement window, starting with the next window.
+    pub fn set_fps_measure_window(&self, fps_measure_window: f32) -> PyResult<()> {
+        if !(fps_measure_window > 0.0) {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Fps measure window must be positive, got {}",
+                fps_measure_window
+            )));
         }
+        self.config.lock().unwrap().fps_measure_window = fps_measure_window;
+        *self.state.fps_measure_window.lock().unwrap() = fps_measure_window;
+        Ok(())
+    }
 
-        // stringly typed spawn description so we don't have to expose the enum to python
-        *AVG_FPS.lock().unwrap() = ups;
-        unsafe {
-            ACTIVE.store(true, Ordering::Relaxed);
-            SHOULD_EXIT.store(false, Ordering::Relaxed);
+    /// Sets the sigma of the gauss filter applied to the spectrum, 0 disables smoothing.
+    pub fn set_smoothing(&self, smoothing: f32) -> PyResult<()> {
+        if !(smoothing >= 0.0 && smoothing.is_finite()) {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Smoothing must be finite and not negative, got {}",
+                smoothing
+            )));
         }
+        self.config.lock().unwrap().smoothing = smoothing;
+        *self.state.smoothing.lock().unwrap() = smoothing;
+        Ok(())
+    }
+
+    /// Dims the whole visualization, 1 being full brightness and 0 black.
+    pub fn set_brightness(&self, brightness: f32) -> PyResult<()> {
+        if !(brightness >= 0.0 && brightness <= 1.0) {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Brightness must be in [0, 1], got {}",
+                brightness
+            )));
+        }
+        self.config.lock().unwrap().brightness = brightness;
+        *self.state.brightness.lock().unwrap() = brightness;
+        Ok(())
+    }
+
+    /// Changes the saturation and value (both in [0, 1]) of the generated colors
+    /// and the hue (in turns) the color cycle starts at. Values that are not given are kept.
+    #[args(saturation = "None", value = "None", hue = "None")]
+    pub fn set_colors(
+        &self,
+        saturation: Option<f32>,
+        value: Option<f32>,
+        hue: Option<f32>,
+    ) -> PyResult<()> {
+        for (name, component) in [("Saturation", saturation), ("Value", value)].iter() {
+            if let Some(component) = component {
+                if !(*component >= 0.0 && *component <= 1.0) {
+                    return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                        "{} must be in [0, 1], got {}",
+                        name, component
+                    )));
+                }
+            }
+        }
+        if let Some(hue) = hue {
+            if !hue.is_finite() {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "Hue must be finite, got {}",
+                    hue
+                )));
+            }
+        }
+        let mut colors = self.state.colors.lock().unwrap();
+        colors.saturation = saturation.unwrap_or(colors.saturation);
+        colors.value = value.unwrap_or(colors.value);
+        colors.hue = hue.unwrap_or(colors.hue);
+        self.config.lock().unwrap().colors = *colors;
+        Ok(())
+    }
+
+    /// Sets a uniform for the shaders of the variant, bound to every program that declares it.
+    /// Accepts ints, floats and tuples of 2 to 4 floats for vec2, vec3 and vec4 uniforms.
+    /// Raises `TypeError` if the running variant declares the uniform with another type,
+    /// values set before the start that do not match are ignored. Takes effect on the next frame.
+    pub fn set_uniform(&self, name: &str, value: &PyAny) -> PyResult<()> {
+        if uniforms::is_builtin(name) {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "{} is supplied by the visualization",
+                name
+            )));
+        }
+        if let Some((slot, _)) =
+            images::slot_of(name, &self.state.declared_image_slots.lock().unwrap())
+        {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "{} belongs to the image slot {}, which is set with set_image",
+                name, slot
+            )));
+        }
+        let value = if let Ok(value) = value.extract::<i32>() {
+            UserUniform::Int(value)
+        } else if let Ok(value) = value.extract::<f32>() {
+            UserUniform::Float(value)
+        } else {
+            match value.extract::<Vec<f32>>()?.as_slice() {
+                [x, y] => UserUniform::Vec2([*x, *y]),
+                [x, y, z] => UserUniform::Vec3([*x, *y, *z]),
+                [x, y, z, w] => UserUniform::Vec4([*x, *y, *z, *w]),
+                values => {
+                    return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                        "Expected 2 to 4 components, got {}",
+                        values.len()
+                    )))
+                }
+            }
+        };
+        let declared = self
+            .state
+            .declared_uniforms
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, ty)| *ty);
+        match declared {
+            Some(ty) if !value.fits(ty) => {
+                return Err(pyo3::exceptions::PyTypeError::new_err(format!(
+                    "{} is declared as {}, got a {}",
+                    name,
+                    uniforms::type_name(ty),
+                    value.type_name()
+                )))
+            }
+            _ => (),
+        }
+        self.state
+            .user_uniforms
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Removes a uniform set with `set_uniform`. Returns whether it was set.
+    pub fn remove_uniform(&self, name: &str) -> bool {
+        self.state.user_uniforms.lock().unwrap().remove(name).is_some()
+    }
+
+    /// Lists the uniforms the shaders of the running variant declare
+    /// that can be set with `set_uniform`, as pairs of name and GLSL type.
+    pub fn get_uniforms(&self) -> Vec<(String, String)> {
+        self.state
+            .declared_uniforms
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, ty)| (name.clone(), uniforms::type_name(*ty)))
+            .collect()
+    }
+
+    /// Switches between fullscreen and windowed mode.
+    /// Can be called while the visualization is running or to configure the next `start`.
+    pub fn set_fullscreen(&self, fullscreen: bool) {
+        self.state.fullscreen.store(fullscreen, Ordering::Relaxed);
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.state.fullscreen.load(Ordering::Relaxed)
+    }
+
+    /// Sets the logical size of the window in windowed mode.
+    /// Takes effect the next time windowed mode is entered.
+    pub fn set_window_size(&self, width: u32, height: u32) {
+        *self.state.window_size.lock().unwrap() = (width, height);
+    }
+
+    /// Renders the visualization at a fraction of the window resolution
+    /// and scales it up with the given filter ("nearest" or "linear").
+    /// Reduces the load on weak GPUs. Takes effect on the next frame.
+    #[args(filter = "\"linear\"")]
+    pub fn set_render_scale(&self, factor: f32, filter: &str) -> PyResult<()> {
+        if !(factor > 0.0 && factor <= 1.0) {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Render scale must be in (0, 1], got {}",
+                factor
+            )));
+        }
+        let filter = filter
+            .parse::<UpscaleFilter>()
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+        *self.state.render_scale.lock().unwrap() = RenderScale {
+            factor: factor,
+            filter: filter,
+        };
+        Ok(())
+    }
+
+    /// Lowers render scale, particle count and shader quality when the fps drop below the target,
+    /// and raises them again once the target is reached.
+    /// Without a target, the expected frame rate of the frame pacing is used.
+    #[args(target_fps = "None")]
+    pub fn set_adaptive_quality(&self, enabled: bool, target_fps: Option<f32>) {
+        *self.state.adaptive_target_fps.lock().unwrap() = if enabled {
+            // a negative target is replaced with the expected frame rate
+            Some(target_fps.unwrap_or(-1.0))
+        } else {
+            None
+        };
+    }
+
+    /// The current adaptive quality level, 0 being the best quality.
+    pub fn get_quality_level(&self) -> usize {
+        self.state.quality_level.load(Ordering::Relaxed)
+    }
+
+    pub fn get_fps(&self) -> f32 {
+        *self.state.avg_fps.lock().unwrap()
+    }
+
+    /// Measures the gpu time of each render pass with timer queries, reported in `get_stats`.
+    /// Has no effect if the driver does not support timer queries.
+    pub fn set_gpu_timing(&self, enabled: bool) {
+        self.state.gpu_timing.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Detailed frame timing statistics of the last fps measurement window.
+    /// None until the first window is finished.
+    pub fn get_stats(&self) -> Option<Stats> {
+        self.state.stats.lock().unwrap().clone()
+    }
+
+    pub fn set_parameters(&self, alarm_factor: f32, current_frame: [f32; BARS as usize]) {
+        self.state.set_input(alarm_factor, current_frame);
+    }
+
+    /// Reads the spectrum from "synthetic", "stdin", "file:<path>" or "udp:<host>:<port>"
+    /// instead of waiting for `set_parameters`. Replaces the previous input source.
+    /// Files and the synthetic spectrum are played back at the given ups.
+    #[args(ups = "30.0")]
+    pub fn set_input(&self, source: &str, ups: f32) -> PyResult<()> {
+        let source = source
+            .parse::<InputSource>()
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+        check_ups(ups).map_err(pyo3::exceptions::PyValueError::new_err)?;
+        self.start_input(source, ups)
+            .map_err(|e| pyo3::exceptions::PyOSError::new_err(e.to_string()))
+    }
+
+    /// Loads a toml config file, see `config::Config` for the format.
+    /// Applies all settings of the file and uses its start parameters for the next `start`.
+    pub fn load_config(&self, path: &str) -> PyResult<()> {
+        let config = Config::load(Path::new(path))
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(format!("{}: {}", path, e)))?;
+        self.apply_config(config)
+            .map_err(|e| pyo3::exceptions::PyOSError::new_err(e.to_string()))
+    }
+
+    /// Shows the image at the given path in the middle of the visualization.
+    /// Takes effect immediately if the visualization is running.
+    pub fn set_logo(&self, path: &str) -> PyResult<()> {
+        let logo =
+            Logo::from_file(Path::new(path)).map_err(pyo3::exceptions::PyOSError::new_err)?;
+        self.state.set_logo(logo);
+        Ok(())
+    }
+
+    /// Shows an image given as raw RGBA pixels, row by row starting at the top,
+    /// e.g. the cover of the current song.
+    pub fn set_logo_rgba(&self, data: &[u8], width: u32, height: u32) -> PyResult<()> {
+        let logo = Logo::from_rgba(data.to_vec(), width, height)
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+        self.state.set_logo(logo);
+        Ok(())
+    }
+
+    /// Shows the raveberry logo again.
+    pub fn reset_logo(&self) {
+        self.state.set_logo(Logo::Default);
+    }
+
+    /// Shows no logo at all, variants then show what is behind it instead.
+    pub fn hide_logo(&self) {
+        self.state.set_logo(Logo::Hidden);
+    }
+
+    /// Shows a PNG or JPEG image in the given slot, e.g. the thumbnail of the current song.
+    /// Variants list the slots they show under `images` in their manifest.
+    /// Shaders access the slot through `uniform sampler2D <slot>`, the image shown before
+    /// through `<slot>_previous` and the progress of the fade between them
+    /// through `uniform float <slot>_transition`.
+    /// The image is decoded in the background, so it shows up after a short delay.
+    /// Images that are replaced before they were decoded are never shown.
+    pub fn set_image(&self, slot: &str, data: &[u8]) -> PyResult<()> {
+        if uniforms::is_builtin(slot) {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "{} is supplied by the visualization",
+                slot
+            )));
+        }
+        images::image_format(data).map_err(pyo3::exceptions::PyValueError::new_err)?;
+        let request = self.state.image_requests.fetch_add(1, Ordering::SeqCst) + 1;
+        let max_size = self.state.max_texture_size.load(Ordering::Relaxed);
+        self.image_decoder.decode(slot, request, data.to_vec(), max_size);
+        Ok(())
+    }
+
+    /// Fades out the image of the given slot.
+    pub fn clear_image(&self, slot: &str) {
+        self.image_decoder.cancel(slot);
+        let request = self.state.image_requests.fetch_add(1, Ordering::SeqCst) + 1;
+        self.state.set_image(slot, request, None);
+    }
+
+    /// Shows text over the visualization, replacing the text with the same name,
+    /// e.g. "title" for the current song, "queue" or "hint".
+    /// `x` and `y` place the `anchor` point of the text box, all as fractions of the screen
+    /// from the top left corner, so `x=1, y=1, anchor=(1, 1)` puts right aligned text
+    /// into the bottom right corner. `size` is the line height as a fraction of the screen height,
+    /// `color` is RGBA and `fade` the seconds the text takes to fade in and out.
+    /// Lines wider than `max_width`, a fraction of the screen width, scroll sideways.
+    #[args(
+        x = "0.05",
+        y = "0.05",
+        anchor = "(0.0, 0.0)",
+        size = "0.05",
+        color = "(1.0, 1.0, 1.0, 1.0)",
+        fade = "0.5",
+        max_width = "None"
+    )]
+    pub fn set_text(
+        &self,
+        name: &str,
+        text: &str,
+        x: f32,
+        y: f32,
+        anchor: (f32, f32),
+        size: f32,
+        color: (f32, f32, f32, f32),
+        fade: f32,
+        max_width: Option<f32>,
+    ) -> PyResult<()> {
+        if !(size > 0.0) || max_width.map_or(false, |w| !(w > 0.0)) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "size and max_width need to be positive",
+            ));
+        }
+        if !(fade >= 0.0) {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "fade must not be negative, got {}",
+                fade
+            )));
+        }
+        self.state.set_text(
+            name,
+            Some(TextOverlay {
+                text: text.to_string(),
+                position: (x, y),
+                anchor: anchor,
+                size: size,
+                color: [color.0, color.1, color.2, color.3],
+                fade: fade,
+                max_width: max_width,
+            }),
+        );
+        Ok(())
+    }
+
+    /// Fades out the text with the given name. Returns whether it was shown.
+    pub fn remove_text(&self, name: &str) -> bool {
+        let shown = matches!(self.state.texts.lock().unwrap().get(name), Some((_, Some(_))));
+        if shown {
+            self.state.set_text(name, None);
+        }
+        shown
+    }
+
+    /// Uses the TrueType or OpenType font at the given path for all text.
+    /// Without a font, one of the common system fonts is used.
+    pub fn set_font(&self, path: &str) -> PyResult<()> {
+        let font = text::load_font(Path::new(path)).map_err(pyo3::exceptions::PyOSError::new_err)?;
+        self.state.set_font(font);
+        Ok(())
+    }
+
+    /// Shows a QR code of the url in the given corner, e.g. so guests can open raveberry.
+    /// `size` is the side length as a fraction of the screen height. The code is shown for
+    /// `visible` seconds, then fades out for `hidden` seconds. With `hidden=0` it stays visible.
+    #[args(corner = "\"bottom_right\"", size = "0.25", visible = "20.0", hidden = "10.0")]
+    pub fn set_qr_code(
+        &self,
+        url: &str,
+        corner: &str,
+        size: f32,
+        visible: f32,
+        hidden: f32,
+    ) -> PyResult<()> {
+        let qr_code = corner
+            .parse::<Corner>()
+            .and_then(|corner| QrOverlay::new(url, corner, size, visible, hidden))
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+        self.state.set_qr_code(Some(qr_code));
+        Ok(())
+    }
+
+    /// Removes the QR code.
+    pub fn clear_qr_code(&self) {
+        self.state.set_qr_code(None);
+    }
+
+    /// Shows a banner with the given text at the top of the screen for `duration` seconds,
+    /// e.g. "New song added by Anna". Banners are shown one after another.
+    /// The style is "info" or "alarm", alarm banners push info banners out
+    /// and are shown before them. Banners are dropped if the visualization is not running.
+    #[args(duration = "5.0", style = "\"info\"")]
+    pub fn notify(&self, text: &str, duration: f32, style: &str) -> PyResult<()> {
+        let style = style
+            .parse::<BannerStyle>()
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+        if !(duration > 0.0) {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "duration must be positive, got {}",
+                duration
+            )));
+        }
+        if self.is_active() {
+            self.state.banners.lock().unwrap().push(Banner {
+                text: text.to_string(),
+                duration: duration,
+                style: style,
+            });
+        }
+        Ok(())
+    }
+
+    /// Stops reading from the source given to `set_input`.
+    pub fn clear_input(&self) {
+        self.state.input_generation.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl Controller {
+    /// Starts the visualization and blocks until its window opened, see `Controller.start`.
+    /// Invalid arguments are reported as `InvalidInput`.
+    pub fn start_visualization(
+        &self,
+        variant: Option<&str>,
+        ups: Option<f32>,
+        num_particles: Option<u32>,
+        fps_measure_window: Option<f32>,
+    ) -> io::Result<()> {
+        let config = self.config.lock().unwrap().clone();
+        let variant = variant.map_or(config.variant, |v| v.to_string());
+        let ups = ups.unwrap_or(config.ups);
+        let num_particles = num_particles.unwrap_or(config.num_particles);
+        let fps_measure_window = fps_measure_window.unwrap_or(config.fps_measure_window);
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        check_ups(ups).map_err(invalid)?;
+        if num_particles > MAX_PARTICLES {
+            return Err(invalid(format!(
+                "Number of particles must be at most {}",
+                MAX_PARTICLES
+            )));
+        }
+
+        let variant = self
+            .variants()
+            .into_iter()
+            .find(|v| v.name == variant)
+            .ok_or_else(|| invalid(format!("Unknown variant given: {}", variant)))?;
+
+        if self.is_active() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "Visualization is already running",
+            ));
+        }
+
+        // the previous render thread has already finished, clean up its handle
+        self.join(Some(Duration::from_secs(0)));
+
+        *self.state.ups.lock().unwrap() = ups;
+        *self.state.num_particles.lock().unwrap() = num_particles;
+        *self.state.fps_measure_window.lock().unwrap() = fps_measure_window;
+        *self.state.avg_fps.lock().unwrap() = ups;
+        *self.state.stats.lock().unwrap() = None;
+        // banners are only meant for the run they were sent to, those sent after this call
+        // are kept even if the render thread did not start yet
+        self.state.banners.lock().unwrap().clear();
+        self.state.set_active(true);
+        self.state.should_exit.store(false, Ordering::Relaxed);
+        let state = Arc::clone(&self.state);
+        let module_root = self.module_root.clone();
+        let monitors = config.monitors;
+        let frame_pacing = config.frame_pacing;
+        let png_sequence = self.png_sequence.lock().unwrap().clone();
+        let benchmark = self.benchmark.load(Ordering::Relaxed);
+        // reports whether the visualization could be created
+        let (started_sender, started) = mpsc::channel();
         // We need the main thread to return, so we give up cross-platform compatibility
         // and commit to unix threads so we can run in a non-main thread.
-        thread::spawn(move || {
+        let render_thread = thread::spawn(move || {
+            let _active_guard = ActiveGuard(Arc::clone(&state));
             // the event_loop can not be part of Visualization because when calling event_loop.run
             // the struct would be moved, including the event_loop, resulting in a partially moved struct
             //let event_loop = glutin::event_loop::EventLoop::new();
             let event_loop = glutin::platform::unix::EventLoopExtUnix::new_any_thread();
-            let visualization =
-                Visualization::new(&event_loop, variant, ups, num_particles, fps_measure_window);
-            visualization.start(event_loop);
+            match Visualization::new(
+                &event_loop,
+                Arc::clone(&state),
+                &module_root,
+                variant,
+                ups,
+                num_particles,
+                fps_measure_window,
+                &monitors,
+                frame_pacing,
+                png_sequence,
+                benchmark,
+            ) {
+                Ok(visualization) => {
+                    let _ = started_sender.send(Ok(()));
+                    visualization.start(event_loop)
+                }
+                Err(e) => {
+                    let _ = started_sender.send(Err(e));
+                }
+            }
+            state.declared_uniforms.lock().unwrap().clear();
+            state.declared_image_slots.lock().unwrap().clear();
             // start a second event loop that does nothing to destroy the previous window
             let mut event_loop: glutin::event_loop::EventLoop<()> =
                 glutin::platform::unix::EventLoopExtUnix::new_any_thread();
             event_loop.run_return(move |_, _, control_flow| {
                 *control_flow = glutin::event_loop::ControlFlow::Exit;
             });
-            unsafe {
-                ACTIVE.store(false, Ordering::Relaxed);
-            }
         });
+        *self.render_thread.lock().unwrap() = Some(render_thread);
+        let error = match started.recv() {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(e)) => e,
+            Err(_) => "The render thread panicked".to_string(),
+        };
+        // the render thread is about to exit, so the visualization is inactive after returning
+        self.join(None);
+        Err(io::Error::new(io::ErrorKind::Other, error))
     }
 
-    pub fn stop(&self) {
-        unsafe {
-            SHOULD_EXIT.store(true, Ordering::Relaxed);
+    /// Makes the next `start` show the synthetic spectrum instead of the input and advance
+    /// the time by 1/ups per frame, so every run renders the same frames.
+    pub fn set_benchmark(&self, benchmark: bool) {
+        self.benchmark.store(benchmark, Ordering::Relaxed);
+    }
+
+    /// Starts reading from the given input source, replacing the previous one.
+    pub fn start_input(&self, source: InputSource, ups: f32) -> io::Result<()> {
+        self.start_inputs(vec![source], ups)
+    }
+
+    /// Starts reading from all given input sources at once, replacing the previous ones.
+    pub fn start_inputs(&self, sources: Vec<InputSource>, ups: f32) -> io::Result<()> {
+        check_ups(ups).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let generation = self.state.input_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        for source in sources {
+            let state = Arc::clone(&self.state);
+            let spawned = input::spawn(source, ups, move |frame| {
+                if state.input_generation.load(Ordering::SeqCst) != generation {
+                    return false;
+                }
+                if let Some(frame) = frame {
+                    state.set_input(frame.alarm_factor, frame.spectrum);
+                }
+                true
+            });
+            if let Err(e) = spawned {
+                // stop the sources that were already started
+                self.clear_input();
+                return Err(e);
+            }
         }
+        Ok(())
     }
 
-    pub fn is_active(&self) -> bool {
-        unsafe { ACTIVE.load(Ordering::Relaxed) }
+    /// Applies all settings of the config, to a running visualization as well.
+    /// The input is only replaced if the config specifies one.
+    pub fn apply_config(&self, config: Config) -> io::Result<()> {
+        // decoded first so nothing is applied if the logo or font is broken
+        let logo = match &config.logo {
+            Some(logo) => Some(
+                Logo::open(logo).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            ),
+            None => None,
+        };
+        let qr_code = match &config.qr_code {
+            Some(qr) => Some(
+                QrOverlay::new(&qr.url, qr.corner, qr.size, qr.visible, qr.hidden)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            ),
+            None => None,
+        };
+        let font = match &config.font {
+            Some(path) => Some(
+                text::load_font(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            ),
+            None => None,
+        };
+        self.set_fullscreen(config.fullscreen);
+        self.set_window_size(config.window_size.0, config.window_size.1);
+        // also applies to a running visualization
+        *self.state.ups.lock().unwrap() = config.ups;
+        *self.state.num_particles.lock().unwrap() = config.num_particles;
+        *self.state.fps_measure_window.lock().unwrap() = config.fps_measure_window;
+        *self.state.smoothing.lock().unwrap() = config.smoothing;
+        *self.state.brightness.lock().unwrap() = config.brightness;
+        *self.state.colors.lock().unwrap() = config.colors;
+        if let Some(logo) = logo {
+            self.state.set_logo(logo);
+        }
+        if let Some(font) = font {
+            self.state.set_font(font);
+        }
+        if qr_code.is_some() {
+            self.state.set_qr_code(qr_code);
+        }
+        let sources = config.input_sources();
+        if !sources.is_empty() {
+            self.start_inputs(sources, config.ups).map_err(|e| {
+                io::Error::new(e.kind(), format!("Could not open input: {}", e))
+            })?;
+        }
+        *self.config.lock().unwrap() = config;
+        Ok(())
     }
 
-    pub fn get_variants(&self) -> Vec<String> {
-        fs::read_dir(format!("{}/shaders/", *MODULE_ROOT.lock().unwrap()))
+    /// Compiles every variant in a headless context, sorted by name.
+    pub fn validate_all(&self) -> Result<Vec<VariantReport>, String> {
+        let event_loop: glutin::event_loop::EventLoop<()> =
+            glutin::platform::unix::EventLoopExtUnix::new_any_thread();
+        let context = glutin::ContextBuilder::new()
+            .build_headless(&event_loop, glutin::dpi::PhysicalSize::new(1, 1))
+            .map_err(|e| format!("Could not create a headless context: {}", e))?;
+        let renderer = glium::HeadlessRenderer::new(context)
+            .map_err(|e| format!("Could not create a headless context: {:?}", e))?;
+        Ok(self
+            .variants()
+            .iter()
+            .map(|variant| validation::validate_variant(&renderer, variant))
+            .collect())
+    }
+
+    /// The directories searched for variants, in order of precedence,
+    /// together with whether they need to exist.
+    pub fn variant_paths(&self) -> Vec<(PathBuf, bool)> {
+        let mut paths = self
+            .config
+            .lock()
             .unwrap()
-            .filter_map(|e| e.ok())
-            .map(|e| e.file_name().to_string_lossy().into_owned())
-            .collect::<Vec<_>>()
+            .variant_paths
+            .iter()
+            .map(|path| (path.clone(), true))
+            .collect::<Vec<_>>();
+        if let Some(user_directory) = variants::user_directory() {
+            paths.push((user_directory, false));
+        }
+        // builtin variants are used if the package directory is missing
+        paths.push((Path::new(&self.module_root).join("shaders"), false));
+        paths
     }
 
-    pub fn get_fps(&self) -> f32 {
-        *AVG_FPS.lock().unwrap()
+    /// The variants that contain all shaders from all search paths, sorted by name.
+    pub fn variants(&self) -> Vec<Variant> {
+        variants::search(&self.variant_paths())
     }
 
-    pub fn set_parameters(&self, alarm_factor: f32, current_frame: [f32; BARS as usize]) {
-        *ALARM_FACTOR.lock().unwrap() = alarm_factor;
-        *CURRENT_FRAME.lock().unwrap() = current_frame;
+    /// Requests the visualization to exit without waiting for it.
+    pub fn request_stop(&self) {
+        self.state.should_exit.store(true, Ordering::Relaxed);
+    }
+
+    /// Blocks until the render thread exited and joins it.
+    /// Returns whether this happened before the timeout passed.
+    pub fn join(&self, timeout: Option<Duration>) -> bool {
+        if !self.state.wait_inactive(timeout) {
+            return false;
+        }
+        if let Some(render_thread) = self.render_thread.lock().unwrap().take() {
+            // the thread is about to finish after marking itself inactive
+            if render_thread.join().is_err() {
+                eprintln!("Render thread panicked");
+            }
+        }
+        true
     }
 }
 
 struct Visualization {
+    state: Arc<SharedState>,
+    // the default logo is reloaded from here when it is restored
+    module_root: String,
+    // the request each image slot was last uploaded from, and when its transition started
+    image_slots: HashMap<String, (usize, f32)>,
+    // text overlays that are shown or fading out, and the request each name was last set by
+    texts: Vec<ShownText>,
+    text_requests: HashMap<String, usize>,
+    // the font and its id, None until text is shown or if no font could be loaded
+    font: Option<(usize, ab_glyph::FontArc)>,
+    font_id: usize,
+    font_missing: bool,
+    // the QR code and when it was set
+    qr_code: Option<(Arc<QrOverlay>, f32)>,
+    banners: BannerQueue,
     ups: f32,
-    resolution: (f32, f32),
-    display: glium::Display,
-    quad_v: glium::VertexBuffer<Vertex>,
-    quad_i: glium::index::NoIndices,
-    background_program: glium::Program,
-    foreground_program: glium::Program,
-    spectrum_texture: glium::texture::Texture2d,
-    logo_texture: glium::texture::Texture2d,
-    particle_v: glium::VertexBuffer<Vertex>,
-    particle_i: glium::index::NoIndices,
-    particle_buffer: glium::VertexBuffer<Particle>,
-    particle_program: glium::Program,
+    outputs: Vec<Output>,
+    pacer: FramePacer,
+    // the refresh rate of the first monitor if frames are synchronized to it
+    refresh_rate: Option<f32>,
+    // the time a frame is expected to take, used to detect late frames
+    target_frame_time: Duration,
+    adaptive_quality: AdaptiveQuality,
+    stats: StatsCollector,
     last_loop: SystemTime,
     time_elapsed: Duration,
-    total_intensity: f32,
+    simulation: Simulation,
+    // the gauss kernel for the current smoothing, recomputed when it changes
+    smoothing: f32,
+    gauss: Vec<f32>,
     fps_counter: u32,
     last_fps_calc: SystemTime,
     fps_measure_window: f32,
+    // set when rendering a png sequence or benchmarking,
+    // which advances the time by a fixed step per frame
+    fixed_frame_time: Option<Duration>,
+    png_output: bool,
+    // shows the synthetic spectrum instead of the input
+    benchmark: bool,
+    remaining_frames: Option<u32>,
 }
 
 impl Visualization {
     fn new(
         event_loop: &glutin::event_loop::EventLoop<()>,
-        variant: String,
+        state: Arc<SharedState>,
+        module_root: &str,
+        variant: Variant,
         ups: f32,
         num_particles: u32,
         fps_measure_window: f32,
-    ) -> Visualization {
-        let monitor_handle = event_loop.primary_monitor().unwrap();
-        let resolution = (
-            monitor_handle.size().width as f32,
-            monitor_handle.size().height as f32,
-        );
-        let fs = glutin::window::Fullscreen::Borderless(Some(monitor_handle));
-        let wb = glutin::window::WindowBuilder::new()
-            .with_inner_size(glium::glutin::dpi::LogicalSize::new(
-                resolution.0,
-                resolution.1,
-            ))
-            .with_fullscreen(Some(fs))
-            .with_title("Raveberry");
-        let cb = glutin::ContextBuilder::new().with_vsync(true);
-        let display = glium::Display::new(wb, cb, &event_loop).unwrap();
-
-        let quad_v: glium::VertexBuffer<Vertex> = glium::VertexBuffer::empty(&display, 3).unwrap();
-        let quad_i = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
-        let quad_vs = fs::read_to_string(format!(
-            "{}/shaders/{}/quad.vs",
-            *MODULE_ROOT.lock().unwrap(),
-            variant
-        ))
-        .expect("Could not read vertex shader");
-        let background_fs = fs::read_to_string(format!(
-            "{}/shaders/{}/background.fs",
-            *MODULE_ROOT.lock().unwrap(),
-            variant
-        ))
-        .expect("Could not read vertex shader");
-        let foreground_fs = fs::read_to_string(format!(
-            "{}/shaders/{}/foreground.fs",
-            *MODULE_ROOT.lock().unwrap(),
-            variant
-        ))
-        .expect("Could not read vertex shader");
-
-        // specify outputs_srgb in every shader for correct color space output
-        // https://github.com/rust-windowing/glutin/issues/1175
-        let background_program = match glium::Program::new(
-            &display,
-            glium::program::ProgramCreationInput::SourceCode {
-                vertex_shader: &quad_vs,
-                tessellation_control_shader: None,
-                tessellation_evaluation_shader: None,
-                geometry_shader: None,
-                fragment_shader: &background_fs,
-                transform_feedback_varyings: None,
-                outputs_srgb: true,
-                uses_point_size: false,
-            },
-        ) {
-            Ok(p) => p,
-            Err(e) => {
-                eprintln!("{}", e);
-                panic!();
+        monitors: &monitor::MonitorSelection,
+        mut frame_pacing: FramePacing,
+        png_sequence: Option<PngSequence>,
+        benchmark: bool,
+    ) -> Result<Visualization, String> {
+        let mut fullscreen = state.fullscreen.load(Ordering::Relaxed);
+        let window_size = *state.window_size.lock().unwrap();
+        let mut placements = monitor::placements(event_loop, monitors)?;
+        if let Some(png_sequence) = &png_sequence {
+            fs::create_dir_all(&png_sequence.directory).map_err(|e| {
+                format!("Could not create {}: {}", png_sequence.directory.display(), e)
+            })?;
+            // a single hidden window of the windowed size, rendering as fast as possible
+            placements.truncate(1);
+            fullscreen = false;
+            frame_pacing = FramePacing::Uncapped;
+        }
+        let capture = png_sequence.as_ref().map(|p| p.directory.clone());
+        state.logo_changed.store(false, Ordering::Relaxed);
+        let logo = state.logo.lock().unwrap().image(module_root)?;
+        // uploaded with the first frame, even if it was already shown by a previous run
+        state.qr_code_changed.store(true, Ordering::Relaxed);
+        let outputs = placements
+            .into_iter()
+            .enumerate()
+            .map(|(index, placement)| {
+                // the windows are swapped one after another, if each of them waited for the
+                // refresh the frame rate would be divided by the number of monitors
+                let pacing = if index == 0 {
+                    frame_pacing
+                } else {
+                    FramePacing::Uncapped
+                };
+                Output::new(
+                    event_loop,
+                    placement,
+                    fullscreen,
+                    window_size,
+                    pacing,
+                    &logo,
+                    &variant,
+                    num_particles,
+                    capture.clone(),
+                )
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        if let Some(output) = outputs.first() {
+            state
+                .max_texture_size
+                .store(output.max_texture_size(), Ordering::Relaxed);
+            // all outputs use the same programs
+            let declared = output.programs().user_uniforms();
+            let user_uniforms = state.user_uniforms.lock().unwrap().clone();
+            // values set before the start could not be checked against the variant yet
+            for (name, ty) in declared.iter() {
+                match user_uniforms.get(name) {
+                    Some(value) if !value.fits(*ty) => eprintln!(
+                        "{}: uniform {} is declared as {}, but was set to a {}",
+                        variant.name,
+                        name,
+                        uniforms::type_name(*ty),
+                        value.type_name()
+                    ),
+                    _ => (),
+                }
             }
-        };
-        let foreground_program = match glium::Program::new(
-            &display,
-            glium::program::ProgramCreationInput::SourceCode {
-                vertex_shader: &quad_vs,
-                tessellation_control_shader: None,
-                tessellation_evaluation_shader: None,
-                geometry_shader: None,
-                fragment_shader: &foreground_fs,
-                transform_feedback_varyings: None,
-                outputs_srgb: true,
-                uses_point_size: false,
-            },
-        ) {
-            Ok(p) => p,
-            Err(e) => {
-                eprintln!("{}", e);
-                panic!();
+            *state.declared_uniforms.lock().unwrap() = declared;
+            *state.declared_image_slots.lock().unwrap() = output.programs().image_slots.clone();
+            let user_uniforms = user_uniforms.keys().cloned().collect::<Vec<_>>();
+            let issues = output.programs().validate(&user_uniforms);
+            for issue in issues.iter() {
+                eprintln!("{}: {}", variant.name, issue);
+            }
+            if issues.iter().any(|i| i.severity == validation::Severity::Error) {
+                return Err(format!(
+                    "{} does not match the interface of the visualization",
+                    variant.name
+                ));
             }
-        };
-
-        let spectrum_texture = glium::texture::Texture2d::empty(&display, BARS, 1).unwrap();
-
-        let image = image::io::Reader::open(format!(
-            "{}/images/raveberry.png",
-            *MODULE_ROOT.lock().unwrap()
-        ))
-        .unwrap()
-        .decode()
-        .unwrap()
-        .to_rgba8();
-        let image_dimensions = image.dimensions();
-        let image =
-            glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-        // don't use an sRGB texture because the shader is already configured to output sRGB
-        let logo_texture = glium::texture::Texture2d::new(&display, image).unwrap();
-
-        let mut vertices: Vec<Vertex> = Vec::new();
-        vertices.push(Vertex {
-            position: [0.0, 0.0],
-        });
-        let particle_v = glium::VertexBuffer::new(&display, &vertices).unwrap();
-        let particle_i = glium::index::NoIndices(glium::index::PrimitiveType::Points);
-
-        let mut particles = Vec::new();
-        for _ in 0..num_particles {
-            let (x, y, z) = if variant == "Circle" {
-                let phi = rand::random::<f32>() * 2.0 * std::f32::consts::PI;
-                let radius_diff = rand::random::<f32>();
-                let resolution_correction = resolution.1 / resolution.0;
-                let x = phi.cos() * (0.6 + radius_diff * 0.2) * resolution_correction;
-                let y = phi.sin() * (0.6 + radius_diff * 0.2);
-                let z = PARTICLE_SPAWN_Z * rand::random::<f32>();
-                (x, y, z)
-            } else if variant == "SnowyCircle" {
-                let x = rand::random::<f32>() * 2. - 1.;
-                let y = rand::random::<f32>() * 4. - 2.;
-                let z = rand::random::<f32>() * 2.;
-                (x, y, z)
-            } else {
-                (0.0, 0.0, 0.0)
-            };
-            let speed = 0.3 * (rand::random::<f32>() * 0.75 + 0.3);
-
-            particles.push(Particle {
-                translation: [x, y],
-                start_z: z,
-                speed: speed,
-            });
         }
 
-        let particle_vs = fs::read_to_string(format!(
-            "{}/shaders/{}/particle.vs",
-            *MODULE_ROOT.lock().unwrap(),
-            variant
-        ))
-        .expect("Could not read vertex shader");
-        let particle_fs = fs::read_to_string(format!(
-            "{}/shaders/{}/particle.fs",
-            *MODULE_ROOT.lock().unwrap(),
-            variant
-        ))
-        .expect("Could not read vertex shader");
-        let particle_program =
-            match glium::Program::from_source(&display, &particle_vs, &particle_fs, None) {
-                Ok(p) => p,
-                Err(e) => {
-                    eprintln!("{}", e);
-                    panic!();
-                }
-            };
-        let particle_buffer = glium::VertexBuffer::dynamic(&display, &particles).unwrap();
+        let refresh_rate = if frame_pacing.uses_vsync() {
+            // only the first window is synchronized, its monitor determines the frame rate
+            outputs.first().and_then(|o| o.refresh_rate())
+        } else {
+            None
+        };
 
-        Visualization {
+        Ok(Visualization {
+            state: state,
+            module_root: module_root.to_string(),
+            image_slots: HashMap::new(),
+            texts: Vec::new(),
+            text_requests: HashMap::new(),
+            font: None,
+            font_id: 0,
+            font_missing: false,
+            qr_code: None,
+            banners: BannerQueue::new(),
             ups: ups,
-            resolution: resolution,
-            display: display,
-            quad_v: quad_v,
-            quad_i: quad_i,
-            background_program: background_program,
-            foreground_program: foreground_program,
-            spectrum_texture: spectrum_texture,
-            logo_texture: logo_texture,
-            particle_v: particle_v,
-            particle_i: particle_i,
-            particle_buffer: particle_buffer,
-            particle_program: particle_program,
+            outputs: outputs,
+            pacer: FramePacer::new(frame_pacing, ups),
+            refresh_rate: refresh_rate,
+            target_frame_time: Duration::from_secs_f32(1.0 / refresh_rate.unwrap_or(ups)),
+            adaptive_quality: AdaptiveQuality::new(),
+            stats: StatsCollector::new(),
             last_loop: SystemTime::now(),
             time_elapsed: Duration::new(0, 0),
-            total_intensity: 0.0,
+            simulation: Simulation::new(),
+            smoothing: -1.0,
+            gauss: Vec::new(),
             fps_counter: 0,
             last_fps_calc: SystemTime::now(),
             fps_measure_window: fps_measure_window,
-        }
+            fixed_frame_time: if png_sequence.is_some() || benchmark {
+                Some(Duration::from_secs_f32(1.0 / ups))
+            } else {
+                None
+            },
+            png_output: png_sequence.is_some(),
+            benchmark: benchmark,
+            remaining_frames: png_sequence.and_then(|p| p.frames),
+        })
     }
 
     fn start(mut self, mut event_loop: glutin::event_loop::EventLoop<()>) {
         event_loop.run_return(move |event, _, control_flow| {
             match event {
-                glutin::event::Event::WindowEvent { event, .. } => match event {
+                glutin::event::Event::WindowEvent { event, window_id } => match event {
+                    // closing any of the windows closes the whole visualization
                     glutin::event::WindowEvent::CloseRequested => {
                         *control_flow = glutin::event_loop::ControlFlow::Exit;
                         return;
                     }
+                    glutin::event::WindowEvent::Resized(size) => {
+                        self.resize(window_id, size);
+                        return;
+                    }
+                    glutin::event::WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        self.resize(window_id, *new_inner_size);
+                        return;
+                    }
                     _ => return,
                 },
                 glutin::event::Event::NewEvents(cause) => match cause {
                     glutin::event::StartCause::ResumeTimeReached { .. } => (),
+                    glutin::event::StartCause::Poll => (),
                     glutin::event::StartCause::Init => (),
                     _ => return,
                 },
                 _ => return,
             }
 
-            if unsafe {
-                SHOULD_EXIT.load(Ordering::Relaxed)
-            } {
+            if self.state.should_exit.load(Ordering::Relaxed) {
                 *control_flow = glutin::event_loop::ControlFlow::Exit;
                 return;
             }
 
-            let next_frame_time =
-                std::time::Instant::now() + std::time::Duration::from_secs_f32(1.0 / self.ups);
-            *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next_frame_time);
-
-            let seconds_elapsed = self.time_elapsed.as_secs_f32();
-
-            let alarm_factor = *ALARM_FACTOR.lock().unwrap();
-            let current_frame_short = (*CURRENT_FRAME.lock().unwrap() as [f32;BARS as usize]).to_vec();
-            // manual implementation of a gauss filter with sigma 1.5, kernel size 11 (4 sigma)
-            // truncate values after 4 sigma -> 7 values of gaussian function (precalculated)
-            // within 1% of scipy's version, good enough for us
-            let gauss = [0.2659615202676218, 0.2129653370149015, 0.10934004978399577, 0.035993977675458706, 0.007597324015864964, 0.001028185997527405, 8.92201505099236e-05];
-            let truncate = gauss.len() - 1;
-
-            // creating a larger vector beforehand to get rid of clamping is not faster
-            // using par_iter is ~5 times slower
-            let current_frame_smooth: Vec<f32> = vec![0.0;BARS as usize].iter().enumerate().map(|(i,_)| {
-                let mut sum: f32 = 0.0;
-                for neighbor in -(truncate as i32)..=truncate as i32 {
-                    let index = std::cmp::max(0, std::cmp::min(current_frame_short.len() - 1, (i as i32 + neighbor).abs() as usize));
-                    sum += gauss[neighbor.abs() as usize] * current_frame_short[index];
-                }
-                sum
-            }).collect();
-
-            // quadruple the frame in size so it matches the rgba texture format
-            let mut current_frame = Vec::with_capacity(BARS as usize * 4);
-            for value in current_frame_smooth {
-                current_frame.push(value);
-                current_frame.push(0.0);
-                current_frame.push(0.0);
-                current_frame.push(0.0);
+            self.update_settings();
+            let frame_due = self.pacer.frame_due();
+            *control_flow = self.pacer.control_flow();
+            if !frame_due {
+                return;
             }
 
-            let mut target = self.display.draw();
-            target.clear_all((0.0, 0.0, 0.0, 1.0), 0.0, 0);
+            if self.remaining_frames == Some(0) {
+                *control_flow = glutin::event_loop::ControlFlow::Exit;
+                return;
+            }
 
-            let mut current_intensity: f32 = current_frame.iter().sum::<f32>() / BARS as f32;
-            if alarm_factor >= 0.0 {
-                current_intensity = alarm_factor;
+            let fullscreen = self.state.fullscreen.load(Ordering::Relaxed) && !self.png_output;
+            let window_size = *self.state.window_size.lock().unwrap();
+            let mut render_scale = *self.state.render_scale.lock().unwrap();
+            render_scale.factor *= self.adaptive_quality.current().render_scale;
+            let num_particles = *self.state.num_particles.lock().unwrap();
+            // taken first so the transitions of new images start in this frame
+            let images = self.take_images();
+            self.update_banners();
+            self.update_texts();
+            let qr_code = self.take_qr_code();
+            let frame = self.compute_frame();
+            let logo = self.take_logo();
+            for output in self.outputs.iter_mut() {
+                if let Some(logo) = &logo {
+                    output.set_logo(logo);
+                }
+                for (slot, image) in images.iter() {
+                    output.set_image(slot, image.as_deref());
+                }
+                if let Some(qr_code) = &qr_code {
+                    output.set_qr_code(qr_code.as_ref().map(|q| &q.image));
+                }
+                output.set_num_particles(num_particles);
+                output.set_fullscreen(fullscreen, window_size);
+                output.set_render_scale(render_scale);
+                let draw_times = output.draw(&frame);
+                self.stats.add_pass_times(draw_times.cpu);
+                for gpu_pass_times in draw_times.gpu {
+                    self.stats.add_gpu_pass_times(gpu_pass_times);
+                }
+                if let Err(e) = output.capture_frame() {
+                    eprintln!("{}", e);
+                    *control_flow = glutin::event_loop::ControlFlow::Exit;
+                    return;
+                }
             }
-            self.total_intensity += current_intensity;
-            // the fraction of time the spectrum was intense
-            let intensity_fraction = self.total_intensity / seconds_elapsed / self.ups;
-
-            // This could easily be computed in the shader,
-            // but due to performance issues on the Pi this was moved to the CPU
-            let shake = ((seconds_elapsed * 9.0 + self.total_intensity * 0.3).cos() * 0.003, (seconds_elapsed * 5.0 + self.total_intensity * 0.3).cos() * 0.003);
-            let saturation = 0.6;
-            let value = 0.7;
-            let start_hue = 0.0;
-            let top_hue = ((seconds_elapsed * 0.15 - self.total_intensity * 0.05) * 0.1 + start_hue) * 360.0;
-            let bot_hue = ((seconds_elapsed * 0.25 + self.total_intensity * 0.05) * 0.02 + start_hue) * 360.0;
-            let mut top_color: Srgb = Hsv::new(top_hue, saturation, value).into_color();
-            let mut bot_color: Srgb = Hsv::new(bot_hue, saturation, value).into_color();
-            if alarm_factor >= 0.0 {
-                top_color = Srgb::new(alarm_factor, 0.0, 0.0);
-                bot_color = Srgb::new(alarm_factor, 0.0, 0.0);
+            self.stats
+                .frame_finished(Instant::now(), self.target_frame_time);
+            if let Some(remaining_frames) = &mut self.remaining_frames {
+                *remaining_frames -= 1;
             }
-            let recent_color = top_color;
-            let past_color: Srgb = Hsv::new(top_hue + 120.0, saturation, value).into_color();
-
-            let image = glium::texture::RawImage2d::from_raw_rgba(current_frame, (BARS, 1));
-            let rect = glium::Rect {
-                left: 0,
-                bottom: 0,
-                width: BARS,
-                height: 1,
-            };
-            self.spectrum_texture.write(rect, image);
-
-            let uniforms = uniform! {
-                RESOLUTION: self.resolution,
-                top_color: (top_color.red, top_color.green, top_color.blue),
-                bot_color: (bot_color.red, bot_color.green, bot_color.blue),
-            };
-            let draw_parameters = glium::DrawParameters {
-                .. Default::default()
-            };
-            target
-                .draw(
-                    &self.quad_v,
-                    &self.quad_i,
-                    &self.background_program,
-                    &uniforms,
-                    &draw_parameters,
-                )
-                .unwrap();
-
-            let uniforms = uniform! {
-                RESOLUTION: self.resolution,
-                PARTICLE_SPAWN_Z: PARTICLE_SPAWN_Z,
-                time_elapsed: seconds_elapsed,
-                intensity_fraction: intensity_fraction,
-            };
-            let draw_parameters = glium::DrawParameters {
-                blend: glium::Blend {
-                    color: glium::BlendingFunction::Addition {
-                        source: glium::LinearBlendingFactor::One,
-                        destination: glium::LinearBlendingFactor::One,
-                    },
-                    alpha: glium::BlendingFunction::Addition {
-                        source: glium::LinearBlendingFactor::One,
-                        destination: glium::LinearBlendingFactor::One,
-                    },
-                    constant_value: (0.0, 0.0, 0.0, 0.0)
-                },
-                point_size: Some(25.0),
-                .. Default::default()
-            };
-            target.draw(
-                (&self.particle_v, self.particle_buffer.per_instance().unwrap()),
-                &self.particle_i,
-                &self.particle_program,
-                &uniforms,
-                &draw_parameters,
-            ).unwrap();
-
-            let uniforms = uniform! {
-                RESOLUTION: self.resolution,
-                BARS: BARS as f32,
-                time_elapsed: seconds_elapsed,
-                current_intensity: current_intensity,
-                shake: shake,
-                recent_color: (recent_color.red, recent_color.green, recent_color.blue),
-                past_color: (past_color.red, past_color.green, past_color.blue),
-                logo: self.logo_texture.sampled().wrap_function(glium::uniforms::SamplerWrapFunction::BorderClamp),
-                spectrum: self.spectrum_texture.sampled().minify_filter(glium::uniforms::MinifySamplerFilter::Linear),
-            };
-            let draw_parameters = glium::DrawParameters {
-                blend: glium::Blend::alpha_blending(),
-                .. Default::default()
-            };
-            target
-                .draw(
-                    &self.quad_v,
-                    &self.quad_i,
-                    &self.foreground_program,
-                    &uniforms,
-                    &draw_parameters,
-                )
-                .unwrap();
-            target.finish().unwrap();
 
             let now = SystemTime::now();
-            self.time_elapsed += now.duration_since(self.last_loop).unwrap();
+            self.time_elapsed += match self.fixed_frame_time {
+                Some(fixed_frame_time) => fixed_frame_time,
+                None => now.duration_since(self.last_loop).unwrap(),
+            };
             self.last_loop = now;
 
             self.fps_counter += 1;
             let since_last_fps_measure = now.duration_since(self.last_fps_calc).unwrap().as_secs_f32();
-            if since_last_fps_measure >= self.fps_measure_window || self.fps_counter as f32 >= self.fps_measure_window * self.ups {
+            // with timer pacing, the window is also finished once the expected number of frames was drawn
+            let expected_frames_drawn = self.pacer.pacing() == FramePacing::Timer && self.fps_counter as f32 >= self.fps_measure_window * self.ups;
+            if since_last_fps_measure >= self.fps_measure_window || expected_frames_drawn {
                 let avg_fps = self.fps_counter as f32 / since_last_fps_measure;
                 self.fps_counter = 0;
                 self.last_fps_calc = now;
-                *AVG_FPS.lock().unwrap() = avg_fps;
+                *self.state.avg_fps.lock().unwrap() = avg_fps;
+                *self.state.stats.lock().unwrap() = Some(self.stats.finish_window(avg_fps));
+
+                match *self.state.adaptive_target_fps.lock().unwrap() {
+                    Some(target_fps) if target_fps > 0.0 => self.adaptive_quality.update(avg_fps, target_fps),
+                    Some(_) => self.adaptive_quality.update(avg_fps, 1.0 / self.target_frame_time.as_secs_f32()),
+                    None => self.adaptive_quality.reset(),
+                }
+                self.state.quality_level.store(self.adaptive_quality.level(), Ordering::Relaxed);
             }
         });
     }
+
+    // Picks up the settings that were changed through the controller since the last frame.
+    fn update_settings(&mut self) {
+        self.fps_measure_window = *self.state.fps_measure_window.lock().unwrap();
+        let ups = *self.state.ups.lock().unwrap();
+        if ups == self.ups {
+            return;
+        }
+        self.ups = ups;
+        self.pacer.set_ups(ups);
+        self.target_frame_time = Duration::from_secs_f32(1.0 / self.refresh_rate.unwrap_or(ups));
+        if let Some(fixed_frame_time) = &mut self.fixed_frame_time {
+            *fixed_frame_time = Duration::from_secs_f32(1.0 / ups);
+        }
+    }
+
+    // The logo to upload if it was replaced since the last frame.
+    fn take_logo(&self) -> Option<image::RgbaImage> {
+        if !self.state.logo_changed.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        let logo = self.state.logo.lock().unwrap().clone();
+        match logo.image(&self.module_root) {
+            Ok(image) => Some(image),
+            Err(e) => {
+                eprintln!("{}", e);
+                None
+            }
+        }
+    }
+
+    // The image slots that changed since the last frame, starting their transition.
+    fn take_images(&mut self) -> Vec<(String, Option<Arc<image::RgbaImage>>)> {
+        let seconds_elapsed = self.time_elapsed.as_secs_f32();
+        let mut changed = Vec::new();
+        for (slot, (request, image)) in self.state.images.lock().unwrap().iter() {
+            if self.image_slots.get(slot).map_or(true, |(r, _)| r != request) {
+                self.image_slots.insert(slot.clone(), (*request, seconds_elapsed));
+                changed.push((slot.clone(), image.clone()));
+            }
+        }
+        changed
+    }
+
+    fn update_banners(&mut self) {
+        for banner in self.state.banners.lock().unwrap().drain(..) {
+            self.banners.push(banner);
+        }
+        self.banners.update(self.time_elapsed.as_secs_f32());
+    }
+
+    // Starts fading in new text overlays and fading out replaced and removed ones.
+    fn update_texts(&mut self) {
+        let seconds_elapsed = self.time_elapsed.as_secs_f32();
+        for (name, (request, overlay)) in self.state.texts.lock().unwrap().iter() {
+            if self.text_requests.get(name) == Some(request) {
+                continue;
+            }
+            self.text_requests.insert(name.clone(), *request);
+            for shown in self.texts.iter_mut().filter(|t| &t.name == name) {
+                shown.hidden_at.get_or_insert(seconds_elapsed);
+            }
+            if let Some(overlay) = overlay {
+                self.texts.push(ShownText {
+                    name: name.clone(),
+                    overlay: Arc::clone(overlay),
+                    shown_at: seconds_elapsed,
+                    hidden_at: None,
+                });
+            }
+        }
+        self.texts.retain(|t| !t.faded_out(seconds_elapsed));
+
+        if self.state.font_changed.swap(false, Ordering::Relaxed) {
+            self.font = None;
+            self.font_missing = false;
+        }
+        let shows_text = !self.texts.is_empty() || self.banners.current(seconds_elapsed).is_some();
+        if self.font.is_none() && !self.font_missing && shows_text {
+            let font = self.state.font.lock().unwrap().clone();
+            match font.map_or_else(text::default_font, Ok) {
+                Ok(font) => {
+                    self.font_id += 1;
+                    self.font = Some((self.font_id, font));
+                }
+                Err(e) => {
+                    // only reported once instead of every frame
+                    eprintln!("{}", e);
+                    self.font_missing = true;
+                }
+            }
+        }
+    }
+
+    // The QR code to upload if it was replaced since the last frame, None if it is unchanged.
+    fn take_qr_code(&mut self) -> Option<Option<Arc<QrOverlay>>> {
+        if !self.state.qr_code_changed.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        let qr_code = self.state.qr_code.lock().unwrap().clone();
+        let seconds_elapsed = self.time_elapsed.as_secs_f32();
+        self.qr_code = qr_code.clone().map(|q| (q, seconds_elapsed));
+        Some(qr_code)
+    }
+
+    fn text_frame(&self) -> Option<TextFrame> {
+        let seconds_elapsed = self.time_elapsed.as_secs_f32();
+        let (font_id, font) = self.font.as_ref()?;
+        let mut texts = self
+            .texts
+            .iter()
+            .map(|shown| TextDraw {
+                overlay: Arc::clone(&shown.overlay),
+                alpha: shown.alpha(seconds_elapsed),
+                scroll_time: seconds_elapsed - shown.shown_at,
+            })
+            .collect::<Vec<_>>();
+        // drawn last so the banner covers other text
+        if let Some(banner) = self.banners.current(seconds_elapsed) {
+            texts.push(banner.text());
+        }
+        if texts.is_empty() {
+            return None;
+        }
+        Some(TextFrame {
+            font_id: *font_id,
+            font: font.clone(),
+            texts: texts,
+        })
+    }
+
+    fn resize(&mut self, window_id: glutin::window::WindowId, size: glutin::dpi::PhysicalSize<u32>) {
+        if let Some(output) = self.outputs.iter_mut().find(|o| o.window_id() == window_id) {
+            output.resize(size);
+        }
+    }
+
+    fn compute_frame(&mut self) -> FrameParameters {
+        let seconds_elapsed = self.time_elapsed.as_secs_f32();
+
+        let (alarm_factor, current_frame_short) = if self.benchmark {
+            (-1.0, input::synthetic_spectrum(seconds_elapsed).to_vec())
+        } else {
+            if let Some(last_input) = *self.state.last_input.lock().unwrap() {
+                self.stats.add_input_staleness(last_input.elapsed());
+            }
+            let alarm_factor = *self.state.alarm_factor.lock().unwrap();
+            let current_frame_short = (*self.state.current_frame.lock().unwrap() as [f32;BARS as usize]).to_vec();
+            (alarm_factor, current_frame_short)
+        };
+        let smoothing_start = Instant::now();
+        let smoothing = *self.state.smoothing.lock().unwrap();
+        if smoothing != self.smoothing {
+            self.smoothing = smoothing;
+            self.gauss = gauss_kernel(smoothing);
+        }
+        let gauss = &self.gauss;
+        let truncate = gauss.len() - 1;
+
+        // creating a larger vector beforehand to get rid of clamping is not faster
+        // using par_iter is ~5 times slower
+        let current_frame_smooth: Vec<f32> = vec![0.0;BARS as usize].iter().enumerate().map(|(i,_)| {
+            let mut sum: f32 = 0.0;
+            for neighbor in -(truncate as i32)..=truncate as i32 {
+                let index = std::cmp::max(0, std::cmp::min(current_frame_short.len() - 1, (i as i32 + neighbor).abs() as usize));
+                sum += gauss[neighbor.abs() as usize] * current_frame_short[index];
+            }
+            sum
+        }).collect();
+
+        // quadruple the frame in size so it matches the rgba texture format
+        let mut current_frame = Vec::with_capacity(BARS as usize * 4);
+        for value in current_frame_smooth {
+            current_frame.push(value);
+            current_frame.push(0.0);
+            current_frame.push(0.0);
+            current_frame.push(0.0);
+        }
+        self.stats.add_smoothing_time(smoothing_start.elapsed());
+
+        let mut current_intensity: f32 = current_frame.iter().sum::<f32>() / BARS as f32;
+        if alarm_factor >= 0.0 {
+            current_intensity = alarm_factor;
+        }
+        // intensity is accumulated in fixed steps independent of the frame rate
+        self.simulation.advance_to(self.time_elapsed, current_intensity);
+        let total_intensity = self.simulation.total_intensity(self.time_elapsed);
+        // the fraction of time the spectrum was intense
+        let intensity_fraction = self.simulation.intensity_fraction();
+
+        // This could easily be computed in the shader,
+        // but due to performance issues on the Pi this was moved to the CPU
+        let shake = ((seconds_elapsed * 9.0 + total_intensity * 0.3).cos() * 0.003, (seconds_elapsed * 5.0 + total_intensity * 0.3).cos() * 0.003);
+        let colors = *self.state.colors.lock().unwrap();
+        let saturation = colors.saturation;
+        let value = colors.value;
+        let start_hue = colors.hue;
+        let top_hue = ((seconds_elapsed * 0.15 - total_intensity * 0.05) * 0.1 + start_hue) * 360.0;
+        let bot_hue = ((seconds_elapsed * 0.25 + total_intensity * 0.05) * 0.02 + start_hue) * 360.0;
+        let mut top_color: Srgb = Hsv::new(top_hue, saturation, value).into_color();
+        let mut bot_color: Srgb = Hsv::new(bot_hue, saturation, value).into_color();
+        if alarm_factor >= 0.0 {
+            top_color = Srgb::new(alarm_factor, 0.0, 0.0);
+            bot_color = Srgb::new(alarm_factor, 0.0, 0.0);
+        }
+        let recent_color = top_color;
+        let past_color: Srgb = Hsv::new(top_hue + 120.0, saturation, value).into_color();
+
+        FrameParameters {
+            seconds_elapsed: seconds_elapsed,
+            current_frame: current_frame,
+            current_intensity: current_intensity,
+            intensity_fraction: intensity_fraction,
+            shake: shake,
+            top_color: top_color,
+            bot_color: bot_color,
+            recent_color: recent_color,
+            past_color: past_color,
+            brightness: *self.state.brightness.lock().unwrap(),
+            quality: self.adaptive_quality.current().shader_quality,
+            particle_fraction: self.adaptive_quality.current().particle_fraction,
+            gpu_timing: self.state.gpu_timing.load(Ordering::Relaxed),
+            user_uniforms: self.state.user_uniforms.lock().unwrap().clone(),
+            image_transitions: self
+                .image_slots
+                .iter()
+                .map(|(slot, (_, start))| {
+                    let transition = (seconds_elapsed - start) / images::TRANSITION_DURATION;
+                    (slot.clone(), transition.min(1.0))
+                })
+                .collect(),
+            text: self.text_frame(),
+            banner: self.banners.current(seconds_elapsed),
+            qr_code: self
+                .qr_code
+                .as_ref()
+                .map(|(qr_code, set_at)| {
+                    (Arc::clone(qr_code), qr_code.alpha(seconds_elapsed - set_at))
+                }),
+        }
+    }
+}
+
+fn check_ups(ups: f32) -> Result<(), String> {
+    if !(ups > 0.0 && ups.is_finite()) {
+        return Err(format!("Ups must be positive and finite, got {}", ups));
+    }
+    Ok(())
+}
+
+// A timeout in seconds as passed from python, None if it is too long to ever pass.
+fn timeout_duration(timeout: Option<f32>) -> Result<Option<Duration>, String> {
+    match timeout {
+        Some(timeout) if !(timeout >= 0.0) => Err(format!(
+            "Timeout must not be negative, got {}",
+            timeout
+        )),
+        Some(timeout) if f64::from(timeout) >= Duration::MAX.as_secs_f64() => Ok(None),
+        timeout => Ok(timeout.map(Duration::from_secs_f32)),
+    }
+}
+
+// Manual implementation of a gauss filter, truncating values after 4 sigma like scipy.
+// Within 1% of scipy's version, good enough for us
+fn gauss_kernel(sigma: f32) -> Vec<f32> {
+    if !(sigma > 0.0) {
+        return vec![1.0];
+    }
+    // the kernel never reaches further than the spectrum
+    let truncate = ((4.0 * sigma).round() as usize).min(BARS as usize);
+    (0..=truncate)
+        .map(|x| {
+            (-((x * x) as f32) / (2.0 * sigma * sigma)).exp()
+                / ((2.0 * std::f32::consts::PI).sqrt() * sigma)
+        })
+        .collect()
 }
 
 #[pyfunction]
 pub fn set_module_root(module_root: &str) {
-    // The image and shaders need to be identified by path
+    // The image and shaders are compiled into the library, but can be overridden on disk
     // In order to find them with a relative path, the root of the module needs to be known
     // Since this is far easier in python, it is passed once during initialization
     *MODULE_ROOT.lock().unwrap() = module_root.to_string();
@@ -511,6 +1719,45 @@ pub fn set_module_root(module_root: &str) {
 #[pymodule]
 fn raveberry_visualization(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<Controller>()?;
+    m.add_class::<Monitor>()?;
+    m.add_class::<Stats>()?;
+    m.add_class::<Variant>()?;
+    m.add_class::<VariantReport>()?;
     m.add_function(wrap_pyfunction!(set_module_root, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gauss_kernel_without_smoothing() {
+        assert_eq!(gauss_kernel(0.0), vec![1.0]);
+        assert_eq!(gauss_kernel(f32::NAN), vec![1.0]);
+    }
+
+    #[test]
+    fn gauss_kernel_truncates_after_4_sigma() {
+        let kernel = gauss_kernel(1.5);
+        assert_eq!(kernel.len(), 7);
+        assert!(kernel.windows(2).all(|w| w[0] > w[1]));
+        // both halves of the kernel together sum to about 1
+        let sum = kernel[0] + 2.0 * kernel[1..].iter().sum::<f32>();
+        assert!((sum - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn gauss_kernel_is_limited_to_the_spectrum() {
+        assert_eq!(gauss_kernel(1e9).len(), BARS as usize + 1);
+        assert_eq!(gauss_kernel(f32::INFINITY).len(), BARS as usize + 1);
+    }
+
+    #[test]
+    fn ups_must_be_positive_and_finite() {
+        assert!(check_ups(30.0).is_ok());
+        assert!(check_ups(0.0).is_err());
+        assert!(check_ups(f32::NAN).is_err());
+        assert!(check_ups(f32::INFINITY).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use crate::assets;
+use image::RgbaImage;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Where the logo in the middle of the visualization is read from, as given in the config.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogoSource {
+    /// the raveberry logo
+    Default,
+    Hidden,
+    File(PathBuf),
+}
+
+impl Default for LogoSource {
+    fn default() -> Self {
+        LogoSource::Default
+    }
+}
+
+impl FromStr for LogoSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(LogoSource::Default),
+            "none" => Ok(LogoSource::Hidden),
+            "" => Err("expected default, none or the path of an image".to_string()),
+            path => Ok(LogoSource::File(PathBuf::from(path))),
+        }
+    }
+}
+
+/// The logo shown by the visualization, already decoded.
+#[derive(Clone, Debug)]
+pub enum Logo {
+    Default,
+    Hidden,
+    Image(Arc<RgbaImage>),
+}
+
+impl Logo {
+    /// Decodes the logo of the given source.
+    pub fn open(source: &LogoSource) -> Result<Logo, String> {
+        match source {
+            LogoSource::Default => Ok(Logo::Default),
+            LogoSource::Hidden => Ok(Logo::Hidden),
+            LogoSource::File(path) => Logo::from_file(path),
+        }
+    }
+
+    /// Decodes an image file in any format supported by the image crate.
+    pub fn from_file(path: &Path) -> Result<Logo, String> {
+        let image = image::io::Reader::open(path)
+            .map_err(|e| e.to_string())
+            .and_then(|reader| reader.with_guessed_format().map_err(|e| e.to_string()))
+            .and_then(|reader| reader.decode().map_err(|e| e.to_string()))
+            .map_err(|e| format!("Could not load {}: {}", path.display(), e))?;
+        Ok(Logo::Image(Arc::new(image.to_rgba8())))
+    }
+
+    /// Wraps raw RGBA pixels, row by row starting at the top.
+    pub fn from_rgba(data: Vec<u8>, width: u32, height: u32) -> Result<Logo, String> {
+        let expected = width as usize * height as usize * 4;
+        if width == 0 || height == 0 || data.len() != expected {
+            return Err(format!(
+                "Expected {} bytes for a {}x{} RGBA image, got {}",
+                expected,
+                width,
+                height,
+                data.len()
+            ));
+        }
+        let image = RgbaImage::from_raw(width, height, data)
+            .ok_or_else(|| "Invalid RGBA image".to_string())?;
+        Ok(Logo::Image(Arc::new(image)))
+    }
+
+    /// The image to upload into the logo texture.
+    pub fn image(&self, module_root: &str) -> Result<RgbaImage, String> {
+        match self {
+            Logo::Default => assets::default_logo(module_root),
+            // the shaders leave out a fully transparent logo, showing the visualization behind it
+            Logo::Hidden => Ok(RgbaImage::new(1, 1)),
+            Logo::Image(image) => Ok(image.as_ref().clone()),
+        }
+    }
+}
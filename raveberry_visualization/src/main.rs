@@ -1,34 +1,503 @@
+use raveberry_visualization::config::{Config, QrCodeConfig};
+use raveberry_visualization::input::InputSource;
+use raveberry_visualization::logo::LogoSource;
+use raveberry_visualization::monitor::MonitorLayout;
+use raveberry_visualization::stats::Stats;
+use raveberry_visualization::{Controller, FramePacing, MAX_PARTICLES};
+use std::env;
+use std::path::Path;
+use std::process;
 use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::Duration;
 
-fn main() {
-    let variant = "Circle";
-    let module_root = "./raveberry_visualization";
-    const UPS: f32 = 30.0;
-    const NUM_PARTICLES: u32 = 400;
-    const FPS_MEASURE_WINDOW: f32 = 5.0;
-    raveberry_visualization::set_module_root(module_root);
-    let controller = raveberry_visualization::Controller {};
-    controller.start(variant, UPS, NUM_PARTICLES, FPS_MEASURE_WINDOW);
-    let mut time_elapsed = Duration::new(0, 0);
-    let mut last_loop = SystemTime::now();
-    loop {
+const USAGE: &str = "Usage:
+    raveberry-visualization [options]
+    raveberry-visualization --list-variants [--module-root PATH] [--variant-path PATH]
+    raveberry-visualization --list-monitors
+    raveberry-visualization validate [--module-root PATH] [--variant-path PATH]
+    raveberry-visualization --benchmark <variant> [--seconds N] [--json] [options]
+
+Options:
+    --config PATH           read the settings from a toml file, other options take precedence
+    --variant NAME          the visualization to show (default: Circle)
+    --module-root PATH      directory whose shaders/ and images/ override the builtin ones
+                            (default: ./raveberry_visualization)
+    --variant-path PATH     additional directory to search for variants, can be repeated
+    --logo PATH             image shown in the middle, default or none (default: default)
+    --font PATH             TrueType or OpenType font for text (default: a common system font)
+    --qr URL                show a QR code of the url in the bottom right corner
+    --ups N                 updates per second (default: 30)
+    --particles N           number of particles (default: 400)
+    --fps-window SECONDS    length of the fps measurement window (default: 5)
+    --monitor LIST          comma separated monitor indices (default: the primary monitor)
+    --layout mirror|extend  how multiple monitors are used (default: mirror)
+    --fullscreen            cover the whole monitor (default)
+    --windowed              show a window instead
+    --window-size WxH       size of the window (default: 1280x720)
+    --pacing MODE           vsync, timer, uncapped or adaptive_vsync (default: vsync)
+    --input SOURCE          synthetic, stdin, file:PATH or udp:HOST:PORT (default: synthetic)
+    --output TARGET         screen or png:DIRECTORY (default: screen)
+    --frames N              stop after N frames when writing pngs
+
+Input other than synthetic is read as one frame per line, consisting of 256 values
+separated by whitespace or commas, optionally preceded by the alarm factor.
+Png output and benchmarks render every frame 1/ups seconds after the previous one,
+benchmarks always show the synthetic spectrum.
+Variants are searched in the --variant-path directories, $XDG_DATA_HOME/raveberry/visualizations
+and the shaders/ directory of the module root, in this order.";
+
+enum Command {
+    Run,
+    ListVariants,
+    ListMonitors,
+    Validate,
+    Benchmark { seconds: f32, json: bool },
+}
+
+struct Options {
+    command: Command,
+    config: Config,
+    module_root: String,
+    // None renders to the screen
+    png_directory: Option<String>,
+    frames: Option<u32>,
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> String {
+    match args.next() {
+        Some(value) => value,
+        None => usage_error(&format!("{} requires a value", flag)),
+    }
+}
+
+fn positive<T>(value: &str, flag: &str) -> T
+where
+    T: std::str::FromStr + PartialOrd + Default + Copy + Into<f64>,
+{
+    match value.parse::<T>() {
+        Ok(value) if value > T::default() && value.into().is_finite() => value,
+        _ => usage_error(&format!("{} requires a positive number", flag)),
+    }
+}
+
+fn parse_size(value: &str) -> Option<(u32, u32)> {
+    let mut parts = value.splitn(2, 'x');
+    let width = parts.next()?.parse::<u32>().ok()?;
+    let height = parts.next()?.parse::<u32>().ok()?;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some((width, height))
+}
+
+/// Loads the config file given with --config, if any.
+fn load_config(args: &[String]) -> Config {
+    let mut config = match args.iter().position(|arg| arg == "--config") {
+        Some(index) => match args.get(index + 1) {
+            Some(path) => match Config::load(Path::new(path)) {
+                Ok(config) => config,
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                    process::exit(1);
+                }
+            },
+            None => usage_error("--config requires a value"),
+        },
+        None => Config::default(),
+    };
+    // without any input, the binary would only show a still image
+    if config.input_sources().is_empty() {
+        config.input = Some(InputSource::Synthetic);
+    }
+    config
+}
+
+fn parse_args() -> Options {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let mut options = Options {
+        command: Command::Run,
+        config: load_config(&args),
+        module_root: "./raveberry_visualization".to_string(),
+        png_directory: None,
+        frames: None,
+    };
+    let config = &mut options.config;
+    let mut args = args.into_iter();
+    let mut seconds = None;
+    let mut json = false;
+    let mut input_given = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // already loaded
+            "--config" => {
+                args.next();
+            }
+            "--variant" => config.variant = value(&mut args, &arg),
+            "--module-root" => options.module_root = value(&mut args, &arg),
+            "--variant-path" => config.variant_paths.push(value(&mut args, &arg).into()),
+            "--logo" => match value(&mut args, &arg).parse::<LogoSource>() {
+                Ok(logo) => config.logo = Some(logo),
+                Err(e) => usage_error(&format!("--logo: {}", e)),
+            },
+            "--font" => config.font = Some(value(&mut args, &arg).into()),
+            "--qr" => config.qr_code = Some(QrCodeConfig::new(&value(&mut args, &arg))),
+            "--ups" => config.ups = positive(&value(&mut args, &arg), &arg),
+            "--particles" => match value(&mut args, &arg).parse::<u32>() {
+                Ok(particles) if particles <= MAX_PARTICLES => config.num_particles = particles,
+                _ => usage_error(&format!(
+                    "--particles requires a number up to {}",
+                    MAX_PARTICLES
+                )),
+            },
+            "--fps-window" => config.fps_measure_window = positive(&value(&mut args, &arg), &arg),
+            "--monitor" => {
+                config.monitors.monitors = match value(&mut args, &arg)
+                    .split(',')
+                    .map(|index| index.trim().parse::<usize>())
+                    .collect::<Result<Vec<_>, _>>()
+                {
+                    Ok(monitors) => monitors,
+                    Err(_) => usage_error("--monitor requires a comma separated list of indices"),
+                }
+            }
+            "--layout" => match value(&mut args, &arg).parse::<MonitorLayout>() {
+                Ok(layout) => config.monitors.layout = layout,
+                Err(e) => usage_error(&e),
+            },
+            "--fullscreen" => config.fullscreen = true,
+            "--windowed" => config.fullscreen = false,
+            "--window-size" => match parse_size(&value(&mut args, &arg)) {
+                Some(size) => config.window_size = size,
+                None => usage_error("--window-size requires a size like 1280x720"),
+            },
+            "--pacing" => match value(&mut args, &arg).parse::<FramePacing>() {
+                Ok(pacing) => config.frame_pacing = pacing,
+                Err(e) => usage_error(&e),
+            },
+            // replaces the input of the config file, including its listeners
+            "--input" => match value(&mut args, &arg).parse::<InputSource>() {
+                Ok(input) => {
+                    config.input = Some(input);
+                    config.listeners.clear();
+                    input_given = true;
+                }
+                Err(e) => usage_error(&e),
+            },
+            "--output" => {
+                let output = value(&mut args, &arg);
+                options.png_directory = if output == "screen" {
+                    None
+                } else if let Some(directory) = output.strip_prefix("png:") {
+                    Some(directory.to_string())
+                } else {
+                    usage_error(&format!("Unknown output: {}", output))
+                };
+            }
+            "--frames" => options.frames = Some(positive(&value(&mut args, &arg), &arg)),
+            "--list-variants" => options.command = Command::ListVariants,
+            "--list-monitors" => options.command = Command::ListMonitors,
+            "validate" => options.command = Command::Validate,
+            "--benchmark" => {
+                config.variant = value(&mut args, &arg);
+                options.command = Command::Benchmark {
+                    seconds: 10.0,
+                    json: false,
+                };
+            }
+            "--seconds" => seconds = Some(positive(&value(&mut args, &arg), &arg)),
+            "--json" => json = true,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => usage_error(&format!("Unknown argument: {}", arg)),
+        }
+    }
+    match &mut options.command {
+        Command::Benchmark {
+            seconds: benchmark_seconds,
+            json: benchmark_json,
+        } => {
+            if options.png_directory.is_some() {
+                usage_error("--benchmark can only render to the screen");
+            }
+            if input_given {
+                usage_error("--input can not be used with --benchmark");
+            }
+            *benchmark_seconds = seconds.unwrap_or(*benchmark_seconds);
+            *benchmark_json = json;
+        }
+        _ if json || seconds.is_some() => {
+            usage_error("--seconds and --json can only be used with --benchmark")
+        }
+        _ => (),
+    }
+    if options.frames.is_some() && options.png_directory.is_none() {
+        usage_error("--frames can only be used with png output");
+    }
+    options
+}
+
+// A controller that searches the variant paths of the config.
+fn variant_searcher(config: &Config) -> Controller {
+    let controller = Controller::new();
+    controller.set_variant_paths(
+        config
+            .variant_paths
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect(),
+    );
+    controller
+}
+
+fn list_variants(options: &Options) {
+    for variant in variant_searcher(&options.config).variants() {
+        if variant.description.is_empty() {
+            println!("{}", variant.name);
+        } else {
+            println!("{}: {}", variant.name, variant.description);
+        }
+    }
+}
+
+fn list_monitors() {
+    for monitor in Controller::new().get_monitors() {
+        println!(
+            "{}: {} ({}x{} at {},{}, {} Hz)",
+            monitor.index,
+            monitor.name,
+            monitor.width,
+            monitor.height,
+            monitor.x,
+            monitor.y,
+            monitor.refresh_rate
+        );
+    }
+}
+
+/// Compiles every variant and exits with an error if any of them failed.
+fn validate(options: &Options) {
+    let reports = match variant_searcher(&options.config).validate_all() {
+        Ok(reports) => reports,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    for report in reports.iter() {
+        println!(
+            "{}: {}",
+            report.variant,
+            if report.passed { "ok" } else { "failed" }
+        );
+        if let Some(log) = &report.log {
+            println!("    {}", log.trim_end().replace('\n', "\n    "));
+        }
+        for message in report.errors.iter().chain(report.warnings.iter()) {
+            println!("    {}", message);
+        }
+    }
+    if reports.iter().any(|r| !r.passed) {
+        process::exit(1);
+    }
+}
+
+/// Creates a controller set up according to the config, reading from the configured input.
+fn controller(config: Config, options: &Options) -> Controller {
+    let controller = variant_searcher(&config);
+    if controller.variants().iter().all(|v| v.name != config.variant) {
+        eprintln!("Unknown variant: {}", config.variant);
+        process::exit(1);
+    }
+    controller.set_png_output(options.png_directory.clone(), options.frames);
+    if let Err(e) = controller.apply_config(config) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    controller
+}
+
+fn run(options: &Options) {
+    let controller = controller(options.config.clone(), options);
+    if let Err(e) = controller.start_visualization(None, None, None, None) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    controller.join(None);
+}
+
+fn benchmark(options: &Options, seconds: f32, json: bool) {
+    let mut config = options.config.clone();
+    config.frame_pacing = FramePacing::Uncapped;
+    // a single measurement window covering the whole benchmark
+    config.fps_measure_window = seconds;
+    // the spectrum is computed from the simulated time of each frame instead
+    config.input = None;
+    config.listeners.clear();
+    let controller = controller(config, options);
+    controller.set_benchmark(true);
+    controller.set_gpu_timing(true);
+    if let Err(e) = controller.start_visualization(None, None, None, None) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    let stats = loop {
         if !controller.is_active() {
-            break;
-        }
-        let seconds_elapsed = time_elapsed.as_secs_f32();
-        let mut current_frame = [0.0; raveberry_visualization::BARS as usize];
-        for (i, val) in current_frame.iter_mut().enumerate() {
-            *val = 0.8
-                * 0.5
-                * (1.0 + (4.0 * seconds_elapsed).sin())
-                * 0.5
-                * (1.0 + (-5.0 * seconds_elapsed + 2.0 * i as f32).sin())
-        }
-        let now = SystemTime::now();
-        time_elapsed += now.duration_since(last_loop).unwrap();
-        last_loop = now;
-        controller.set_parameters(-1.0, current_frame);
-        thread::sleep(Duration::from_secs_f32(1.0 / UPS as f32));
+            eprintln!("The visualization exited before the benchmark finished");
+            process::exit(1);
+        }
+        if let Some(stats) = controller.get_stats() {
+            break stats;
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+    controller.request_stop();
+    controller.join(None);
+
+    if json {
+        println!("{}", stats_json(&options.config.variant, seconds, &stats));
+    } else {
+        print_stats(&options.config.variant, seconds, &stats);
+    }
+}
+
+// JSON has no representation for infinity and NaN, they are written as null like missing values.
+fn json_number(value: f32) -> String {
+    if value.is_finite() {
+        format!("{:.3}", value)
+    } else {
+        "null".to_string()
+    }
+}
+
+fn format_optional(value: Option<f32>) -> String {
+    value.map_or_else(|| "null".to_string(), json_number)
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c < ' ' || c == '\u{7f}' => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn stats_json(variant: &str, seconds: f32, stats: &Stats) -> String {
+    format!(
+        concat!(
+            "{{\"variant\": {}, \"seconds\": {}, \"frames\": {}, \"fps\": {}, ",
+            "\"frame_time\": {{\"mean\": {}, \"p50\": {}, \"p95\": {}, \"p99\": {}, \"max\": {}}}, ",
+            "\"late_frames\": {}, \"dropped_frames\": {}, \"smoothing_time\": {}, ",
+            "\"cpu_pass_time\": {{\"background\": {}, \"particles\": {}, \"foreground\": {}, \"present\": {}}}, ",
+            "\"gpu_pass_time\": {{\"background\": {}, \"particles\": {}, \"foreground\": {}}}}}"
+        ),
+        json_string(variant),
+        json_number(seconds),
+        stats.frames,
+        json_number(stats.fps),
+        json_number(1000.0 / stats.fps),
+        json_number(stats.frame_time_p50),
+        json_number(stats.frame_time_p95),
+        json_number(stats.frame_time_p99),
+        json_number(stats.frame_time_max),
+        stats.late_frames,
+        stats.dropped_frames,
+        json_number(stats.smoothing_time),
+        json_number(stats.background_pass_time),
+        json_number(stats.particle_pass_time),
+        json_number(stats.foreground_pass_time),
+        json_number(stats.present_time),
+        format_optional(stats.gpu_background_pass_time),
+        format_optional(stats.gpu_particle_pass_time),
+        format_optional(stats.gpu_foreground_pass_time),
+    )
+}
+
+fn print_stats(variant: &str, seconds: f32, stats: &Stats) {
+    println!(
+        "Benchmark of {} over {} s ({} frames)",
+        variant, seconds, stats.frames
+    );
+    println!("fps:         {:.1}", stats.fps);
+    println!(
+        "frame time:  mean {:.2} ms, p50 {:.2} ms, p95 {:.2} ms, p99 {:.2} ms, max {:.2} ms",
+        1000.0 / stats.fps,
+        stats.frame_time_p50,
+        stats.frame_time_p95,
+        stats.frame_time_p99,
+        stats.frame_time_max
+    );
+    println!(
+        "late frames: {} ({} dropped)",
+        stats.late_frames, stats.dropped_frames
+    );
+    println!("smoothing:   {:.3} ms", stats.smoothing_time);
+    println!(
+        "cpu passes:  background {:.3} ms, particles {:.3} ms, foreground {:.3} ms, present {:.3} ms",
+        stats.background_pass_time,
+        stats.particle_pass_time,
+        stats.foreground_pass_time,
+        stats.present_time
+    );
+    match (
+        stats.gpu_background_pass_time,
+        stats.gpu_particle_pass_time,
+        stats.gpu_foreground_pass_time,
+    ) {
+        (Some(background), Some(particles), Some(foreground)) => println!(
+            "gpu passes:  background {:.3} ms, particles {:.3} ms, foreground {:.3} ms",
+            background, particles, foreground
+        ),
+        _ => println!("gpu passes:  not available"),
+    }
+}
+
+fn main() {
+    let options = parse_args();
+    raveberry_visualization::set_module_root(&options.module_root);
+    match options.command {
+        Command::Run => run(&options),
+        Command::ListVariants => list_variants(&options),
+        Command::ListMonitors => list_monitors(),
+        Command::Validate => validate(&options),
+        Command::Benchmark { seconds, json } => benchmark(&options, seconds, json),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(json_string("Circle"), "\"Circle\"");
+        assert_eq!(json_string("a \"b\" \\ c"), "\"a \\\"b\\\" \\\\ c\"");
+        assert_eq!(json_string("line\nbell\u{7}"), "\"line\\nbell\\u0007\"");
+        assert_eq!(json_string("Kreis ö"), "\"Kreis ö\"");
+    }
+
+    #[test]
+    fn writes_non_finite_numbers_as_null() {
+        assert_eq!(json_number(1.5), "1.500");
+        assert_eq!(json_number(f32::INFINITY), "null");
+        assert_eq!(json_number(f32::NAN), "null");
+        assert_eq!(format_optional(Some(f32::NAN)), "null");
+        assert_eq!(format_optional(None), "null");
     }
 }
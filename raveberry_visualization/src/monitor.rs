@@ -0,0 +1,184 @@
+use glium::glutin;
+use glutin::dpi::{PhysicalPosition, PhysicalSize};
+use glutin::monitor::MonitorHandle;
+use pyo3::prelude::*;
+use std::str::FromStr;
+
+/// A monitor connected to this machine, as reported to python.
+#[pyclass]
+#[derive(Clone)]
+pub struct Monitor {
+    #[pyo3(get)]
+    pub index: usize,
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub width: u32,
+    #[pyo3(get)]
+    pub height: u32,
+    /// in Hz, 0 if it could not be determined
+    #[pyo3(get)]
+    pub refresh_rate: u16,
+    #[pyo3(get)]
+    pub x: i32,
+    #[pyo3(get)]
+    pub y: i32,
+}
+
+#[pymethods]
+impl Monitor {
+    fn __repr__(&self) -> String {
+        format!(
+            "Monitor({}, {:?}, {}x{}@{}Hz, +{}+{})",
+            self.index, self.name, self.width, self.height, self.refresh_rate, self.x, self.y
+        )
+    }
+}
+
+impl Monitor {
+    fn from_handle(index: usize, handle: &MonitorHandle) -> Monitor {
+        let size = handle.size();
+        let position = handle.position();
+        Monitor {
+            index: index,
+            name: handle
+                .name()
+                .unwrap_or_else(|| format!("Monitor {}", index)),
+            width: size.width,
+            height: size.height,
+            refresh_rate: refresh_rate(handle),
+            x: position.x,
+            y: position.y,
+        }
+    }
+}
+
+/// The refresh rate of the monitor in Hz, 0 if it could not be determined.
+pub fn refresh_rate(handle: &MonitorHandle) -> u16 {
+    // winit does not report the current mode, so use the fastest mode with the current size
+    let size = handle.size();
+    handle
+        .video_modes()
+        .filter(|mode| mode.size() == size)
+        .map(|mode| mode.refresh_rate())
+        .max()
+        .unwrap_or(0)
+}
+
+pub fn list<T>(event_loop: &glutin::event_loop::EventLoop<T>) -> Vec<Monitor> {
+    event_loop
+        .available_monitors()
+        .enumerate()
+        .map(|(index, handle)| Monitor::from_handle(index, &handle))
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MonitorLayout {
+    /// one fullscreen window per monitor, all showing the same visualization
+    Mirror,
+    /// a single borderless window spanning all monitors
+    Extend,
+}
+
+impl FromStr for MonitorLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mirror" => Ok(MonitorLayout::Mirror),
+            "extend" => Ok(MonitorLayout::Extend),
+            _ => Err(format!("Unknown monitor layout: {}", s)),
+        }
+    }
+}
+
+/// Which monitors to render to. An empty selection means the primary monitor.
+#[derive(Clone, Debug)]
+pub struct MonitorSelection {
+    pub monitors: Vec<usize>,
+    pub layout: MonitorLayout,
+}
+
+impl Default for MonitorSelection {
+    fn default() -> Self {
+        MonitorSelection {
+            monitors: Vec::new(),
+            layout: MonitorLayout::Mirror,
+        }
+    }
+}
+
+/// Where a single window of the visualization is placed.
+pub enum Placement {
+    Fullscreen(MonitorHandle),
+    Spanning {
+        position: PhysicalPosition<i32>,
+        size: PhysicalSize<u32>,
+    },
+}
+
+impl Placement {
+    pub fn position(&self) -> PhysicalPosition<i32> {
+        match self {
+            Placement::Fullscreen(handle) => handle.position(),
+            Placement::Spanning { position, .. } => *position,
+        }
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        match self {
+            Placement::Fullscreen(handle) => handle.size(),
+            Placement::Spanning { size, .. } => *size,
+        }
+    }
+}
+
+/// Computes the windows needed to cover the selected monitors.
+pub fn placements<T>(
+    event_loop: &glutin::event_loop::EventLoop<T>,
+    selection: &MonitorSelection,
+) -> Result<Vec<Placement>, String> {
+    let available: Vec<MonitorHandle> = event_loop.available_monitors().collect();
+    let mut handles = Vec::new();
+    for &index in &selection.monitors {
+        match available.get(index) {
+            Some(handle) => handles.push(handle.clone()),
+            None => {
+                return Err(format!(
+                    "Unknown monitor {}, only {} available",
+                    index,
+                    available.len()
+                ))
+            }
+        }
+    }
+    if handles.is_empty() {
+        match event_loop.primary_monitor().or_else(|| available.first().cloned()) {
+            Some(handle) => handles.push(handle),
+            None => return Err("No monitor found".to_string()),
+        }
+    }
+
+    if handles.len() == 1 || selection.layout == MonitorLayout::Mirror {
+        return Ok(handles.into_iter().map(Placement::Fullscreen).collect());
+    }
+
+    // the bounding box of all selected monitors
+    let left = handles.iter().map(|h| h.position().x).min().unwrap();
+    let top = handles.iter().map(|h| h.position().y).min().unwrap();
+    let right = handles
+        .iter()
+        .map(|h| h.position().x + h.size().width as i32)
+        .max()
+        .unwrap();
+    let bottom = handles
+        .iter()
+        .map(|h| h.position().y + h.size().height as i32)
+        .max()
+        .unwrap();
+    Ok(vec![Placement::Spanning {
+        position: PhysicalPosition::new(left, top),
+        size: PhysicalSize::new((right - left) as u32, (bottom - top) as u32),
+    }])
+}
@@ -0,0 +1,796 @@
+use crate::banner::BannerDraw;
+use crate::gpu_timer::{GpuTimers, PassQueries};
+use crate::images::{self, ImageTextures};
+use crate::overlay::{OverlayRenderer, Quad};
+use crate::monitor::{self, Placement};
+use crate::pacing::{self, FramePacing};
+use crate::qr::QrOverlay;
+use crate::stats::PassTimes;
+use crate::text::{GlyphAtlas, TextFrame};
+use crate::shaders::Programs;
+use crate::uniforms::{UserUniform, WithUserUniforms};
+use crate::variants::Variant;
+use crate::{Particle, Vertex, BARS, PARTICLE_SPAWN_Z};
+use glium::uniforms::{UniformType, Uniforms};
+use glium::{glutin, Surface};
+use image::RgbaImage;
+use palette::Srgb;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UpscaleFilter {
+    Nearest,
+    Linear,
+}
+
+impl FromStr for UpscaleFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(UpscaleFilter::Nearest),
+            "linear" => Ok(UpscaleFilter::Linear),
+            _ => Err(format!("Unknown upscale filter: {}", s)),
+        }
+    }
+}
+
+impl From<UpscaleFilter> for glium::uniforms::MagnifySamplerFilter {
+    fn from(filter: UpscaleFilter) -> Self {
+        match filter {
+            UpscaleFilter::Nearest => glium::uniforms::MagnifySamplerFilter::Nearest,
+            UpscaleFilter::Linear => glium::uniforms::MagnifySamplerFilter::Linear,
+        }
+    }
+}
+
+/// The fraction of the window resolution the passes are rendered at,
+/// and how the result is scaled up to the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderScale {
+    pub factor: f32,
+    pub filter: UpscaleFilter,
+}
+
+impl Default for RenderScale {
+    fn default() -> Self {
+        RenderScale {
+            factor: 1.0,
+            filter: UpscaleFilter::Linear,
+        }
+    }
+}
+
+/// Renders into numbered png files instead of the screen.
+#[derive(Clone, Debug)]
+pub struct PngSequence {
+    pub directory: PathBuf,
+    /// the number of frames to render, unlimited if None
+    pub frames: Option<u32>,
+}
+
+/// Everything that is computed once per frame and shared by all outputs.
+pub struct FrameParameters {
+    pub seconds_elapsed: f32,
+    /// the smoothed spectrum in rgba texture format
+    pub current_frame: Vec<f32>,
+    pub current_intensity: f32,
+    pub intensity_fraction: f32,
+    pub shake: (f32, f32),
+    pub top_color: Srgb,
+    pub bot_color: Srgb,
+    pub recent_color: Srgb,
+    pub past_color: Srgb,
+    /// multiplies the final image, in [0, 1]
+    pub brightness: f32,
+    /// shader quality tier, lower is cheaper
+    pub quality: i32,
+    /// the fraction of particles to draw
+    pub particle_fraction: f32,
+    pub gpu_timing: bool,
+    /// values set through `Controller.set_uniform`
+    pub user_uniforms: HashMap<String, UserUniform>,
+    /// how far each image slot faded to its current image, in [0, 1]
+    pub image_transitions: HashMap<String, f32>,
+    /// None if no text is shown
+    pub text: Option<TextFrame>,
+    /// the QR code and its opacity, None if no code is set
+    pub qr_code: Option<(Arc<QrOverlay>, f32)>,
+    /// the banner passed to notify that is currently shown
+    pub banner: Option<BannerDraw>,
+}
+
+// The rust type a uniform of the given GLSL type is supplied as.
+macro_rules! uniform_type {
+    (Float) => { f32 };
+    (FloatVec2) => { (f32, f32) };
+    (FloatVec3) => { (f32, f32, f32) };
+    (Int) => { i32 };
+    (Sampler2d) => { glium::uniforms::Sampler<'_, glium::texture::Texture2d> };
+}
+
+// Declares the uniforms a pass supplies in one place: as the list of names and types
+// variants are validated against and as the function building the values they are drawn with.
+macro_rules! pass_uniforms {
+    (
+        $list:ident,
+        fn $function:ident<$lifetime:lifetime>($($arg:ident: $arg_type:ty),* $(,)?) {
+            $($name:ident: $type:ident = $value:expr,)*
+        }
+    ) => {
+        pub const $list: &[(&str, UniformType)] = &[$((stringify!($name), UniformType::$type)),*];
+
+        fn $function<$lifetime>($($arg: $arg_type),*) -> impl Uniforms + $lifetime {
+            uniform! {
+                $($name: {
+                    let value: uniform_type!($type) = $value;
+                    value
+                }),*
+            }
+        }
+    };
+}
+
+fn rgb(color: Srgb) -> (f32, f32, f32) {
+    (color.red, color.green, color.blue)
+}
+
+pass_uniforms!(
+    BACKGROUND_UNIFORMS,
+    fn background_uniforms<'a>(resolution: (f32, f32), frame: &'a FrameParameters) {
+        RESOLUTION: FloatVec2 = resolution,
+        top_color: FloatVec3 = rgb(frame.top_color),
+        bot_color: FloatVec3 = rgb(frame.bot_color),
+        quality: Int = frame.quality,
+    }
+);
+
+pass_uniforms!(
+    PARTICLE_UNIFORMS,
+    fn particle_uniforms<'a>(resolution: (f32, f32), frame: &'a FrameParameters) {
+        RESOLUTION: FloatVec2 = resolution,
+        PARTICLE_SPAWN_Z: Float = PARTICLE_SPAWN_Z,
+        time_elapsed: Float = frame.seconds_elapsed,
+        intensity_fraction: Float = frame.intensity_fraction,
+        quality: Int = frame.quality,
+    }
+);
+
+pass_uniforms!(
+    FOREGROUND_UNIFORMS,
+    fn foreground_uniforms<'a>(
+        resolution: (f32, f32),
+        frame: &'a FrameParameters,
+        logo: &'a glium::texture::Texture2d,
+        logo_alpha: f32,
+        spectrum: &'a glium::texture::Texture2d,
+    ) {
+        RESOLUTION: FloatVec2 = resolution,
+        BARS: Float = BARS as f32,
+        time_elapsed: Float = frame.seconds_elapsed,
+        current_intensity: Float = frame.current_intensity,
+        shake: FloatVec2 = frame.shake,
+        recent_color: FloatVec3 = rgb(frame.recent_color),
+        past_color: FloatVec3 = rgb(frame.past_color),
+        logo: Sampler2d = logo.sampled().wrap_function(glium::uniforms::SamplerWrapFunction::BorderClamp),
+        logo_alpha: Float = logo_alpha,
+        spectrum: Sampler2d = spectrum.sampled().minify_filter(glium::uniforms::MinifySamplerFilter::Linear),
+        quality: Int = frame.quality,
+    }
+);
+
+pass_uniforms!(
+    DIM_UNIFORMS,
+    fn dim_uniforms<'a>(resolution: (f32, f32), frame: &'a FrameParameters) {
+        RESOLUTION: FloatVec2 = resolution,
+        brightness: Float = frame.brightness,
+    }
+);
+
+/// Time measurements of a single drawn frame.
+pub struct DrawTimes {
+    /// cpu time spent issuing each pass
+    pub cpu: PassTimes,
+    /// gpu time of the passes of previous frames whose timer queries finished by now
+    pub gpu: Vec<PassTimes>,
+}
+
+// The quads drawn over a frame, grouped by texture.
+struct Overlays {
+    text: Vec<Quad>,
+    qr_code: Vec<Quad>,
+    banner: Vec<Quad>,
+}
+
+/// A single window together with all the GL resources needed to draw into it.
+pub struct Output {
+    variant: String,
+    placement: Placement,
+    fullscreen: bool,
+    resolution: (f32, f32),
+    render_scale: RenderScale,
+    // the offscreen target the passes are drawn into if the render scale is below 1 or frames are captured
+    scaled_target: Option<glium::texture::Texture2d>,
+    // the directory captured frames are saved to, and the number of the next one
+    capture: Option<(PathBuf, u32)>,
+    // None if gpu timing is disabled or not supported
+    gpu_timers: Option<GpuTimers>,
+    gpu_timing_supported: bool,
+    display: glium::Display,
+    quad_v: glium::VertexBuffer<Vertex>,
+    quad_i: glium::index::NoIndices,
+    programs: Programs,
+    spectrum_texture: glium::texture::Texture2d,
+    logo_texture: glium::texture::Texture2d,
+    // 0 if the logo is hidden
+    logo_alpha: f32,
+    images: ImageTextures,
+    overlay: OverlayRenderer,
+    glyph_atlas: GlyphAtlas,
+    qr_texture: Option<glium::texture::Texture2d>,
+    particle_v: glium::VertexBuffer<Vertex>,
+    particle_i: glium::index::NoIndices,
+    particle_buffer: glium::VertexBuffer<Particle>,
+    // the buffer always holds at least one particle, as empty buffers can not be created
+    num_particles: u32,
+}
+
+impl Output {
+    pub fn new(
+        event_loop: &glutin::event_loop::EventLoop<()>,
+        placement: Placement,
+        fullscreen: bool,
+        windowed_size: (u32, u32),
+        pacing: FramePacing,
+        logo: &RgbaImage,
+        variant: &Variant,
+        num_particles: u32,
+        capture: Option<PathBuf>,
+    ) -> Result<Output, String> {
+        let wb = glutin::window::WindowBuilder::new()
+            .with_title("Raveberry")
+            .with_visible(capture.is_none());
+        let wb = if fullscreen {
+            match &placement {
+                Placement::Fullscreen(monitor_handle) => {
+                    let size = monitor_handle.size();
+                    let fs = glutin::window::Fullscreen::Borderless(Some(monitor_handle.clone()));
+                    wb.with_inner_size(glium::glutin::dpi::LogicalSize::new(
+                        size.width as f32,
+                        size.height as f32,
+                    ))
+                    .with_fullscreen(Some(fs))
+                }
+                // borderless fullscreen is limited to a single monitor,
+                // so cover all of them with an undecorated window instead
+                Placement::Spanning { position, size } => wb
+                    .with_decorations(false)
+                    .with_position(*position)
+                    .with_inner_size(*size),
+            }
+        } else {
+            wb.with_position(placement.position())
+                .with_inner_size(glium::glutin::dpi::LogicalSize::new(
+                    windowed_size.0,
+                    windowed_size.1,
+                ))
+        };
+        let cb = glutin::ContextBuilder::new().with_vsync(pacing.uses_vsync());
+        let display = glium::Display::new(wb, cb, &event_loop)
+            .map_err(|e| format!("Could not create window: {}", e))?;
+        if pacing == FramePacing::AdaptiveVsync {
+            if let Err(e) = pacing::enable_adaptive_vsync(&display) {
+                eprintln!("{}, falling back to vsync", e);
+            }
+        }
+        let size = if fullscreen {
+            placement.size()
+        } else {
+            display.gl_window().window().inner_size()
+        };
+        let resolution = (size.width as f32, size.height as f32);
+
+        let quad_v: glium::VertexBuffer<Vertex> = glium::VertexBuffer::empty(&display, 3).unwrap();
+        let quad_i = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+        let programs = Programs::compile(&display, variant)
+            .map_err(|e| format!("Could not compile {}: {}", variant.name, e))?;
+
+        let spectrum_texture = glium::texture::Texture2d::empty(&display, BARS, 1).unwrap();
+
+        let (logo_texture, logo_alpha) = match images::upload(&display, logo) {
+            Ok(texture) => (texture, logo_alpha(logo)),
+            Err(e) => {
+                eprintln!("logo: {}", e);
+                (images::upload(&display, &RgbaImage::new(1, 1)).unwrap(), 0.0)
+            }
+        };
+        let images = ImageTextures::new(&display);
+        let overlay = OverlayRenderer::new(&display)?;
+        let glyph_atlas = GlyphAtlas::new(&display);
+
+        let mut vertices: Vec<Vertex> = Vec::new();
+        vertices.push(Vertex {
+            position: [0.0, 0.0],
+        });
+        let particle_v = glium::VertexBuffer::new(&display, &vertices).unwrap();
+        let particle_i = glium::index::NoIndices(glium::index::PrimitiveType::Points);
+
+        let particles = spawn_particles(&variant.name, resolution, num_particles.max(1));
+        let particle_buffer = glium::VertexBuffer::dynamic(&display, &particles).unwrap();
+
+        let mut output = Output {
+            variant: variant.name.clone(),
+            placement: placement,
+            fullscreen: fullscreen,
+            resolution: resolution,
+            render_scale: RenderScale::default(),
+            scaled_target: None,
+            capture: capture.map(|directory| (directory, 0)),
+            gpu_timers: None,
+            gpu_timing_supported: true,
+            display: display,
+            quad_v: quad_v,
+            quad_i: quad_i,
+            programs: programs,
+            spectrum_texture: spectrum_texture,
+            logo_texture: logo_texture,
+            logo_alpha: logo_alpha,
+            images: images,
+            overlay: overlay,
+            glyph_atlas: glyph_atlas,
+            qr_texture: None,
+            particle_v: particle_v,
+            particle_i: particle_i,
+            particle_buffer: particle_buffer,
+            num_particles: num_particles,
+        };
+        output.allocate_scaled_target();
+        Ok(output)
+    }
+
+    pub fn programs(&self) -> &Programs {
+        &self.programs
+    }
+
+    /// The refresh rate of the monitor the window is on, if known.
+    pub fn refresh_rate(&self) -> Option<f32> {
+        let refresh_rate = monitor::refresh_rate(&self.display.gl_window().window().current_monitor()?);
+        if refresh_rate == 0 {
+            return None;
+        }
+        Some(refresh_rate as f32)
+    }
+
+    pub fn window_id(&self) -> glutin::window::WindowId {
+        self.display.gl_window().window().id()
+    }
+
+    /// Updates the resolution after the window changed its size or scale factor.
+    pub fn resize(&mut self, size: glutin::dpi::PhysicalSize<u32>) {
+        // minimized windows report a size of zero
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        let resolution = (size.width as f32, size.height as f32);
+        if resolution == self.resolution {
+            return;
+        }
+        self.resolution = resolution;
+        self.allocate_scaled_target();
+        // the spawn positions of some variants depend on the aspect ratio
+        let particles =
+            spawn_particles(&self.variant, self.resolution, self.particle_buffer.len() as u32);
+        self.particle_buffer.write(&particles);
+    }
+
+    /// Replaces the logo texture, keeping the previous one if the upload fails.
+    pub fn set_logo(&mut self, logo: &RgbaImage) {
+        match images::upload(&self.display, logo) {
+            Ok(texture) => {
+                self.logo_texture = texture;
+                self.logo_alpha = logo_alpha(logo);
+            }
+            Err(e) => eprintln!("logo: {}", e),
+        }
+    }
+
+    /// The longest side of an image that can be uploaded without scaling it down.
+    pub fn max_texture_size(&self) -> u32 {
+        images::max_texture_size(&self.display)
+    }
+
+    /// Replaces the image of a slot, the previous one stays available for the transition.
+    pub fn set_image(&mut self, slot: &str, image: Option<&RgbaImage>) {
+        self.images.set(&self.display, slot, image);
+    }
+
+    /// Replaces the texture of the QR code, None removes it.
+    pub fn set_qr_code(&mut self, image: Option<&RgbaImage>) {
+        self.qr_texture = image.map(|image| {
+            let raw = glium::texture::RawImage2d::from_raw_rgba(image.to_vec(), image.dimensions());
+            glium::texture::Texture2d::new(&self.display, raw).unwrap()
+        });
+    }
+
+    /// Reallocates the particle buffer if the number of particles changed.
+    pub fn set_num_particles(&mut self, num_particles: u32) {
+        if num_particles == self.num_particles {
+            return;
+        }
+        self.num_particles = num_particles;
+        let particles = spawn_particles(&self.variant, self.resolution, num_particles.max(1));
+        self.particle_buffer = glium::VertexBuffer::dynamic(&self.display, &particles).unwrap();
+    }
+
+    pub fn set_render_scale(&mut self, render_scale: RenderScale) {
+        if render_scale == self.render_scale {
+            return;
+        }
+        self.render_scale = render_scale;
+        self.allocate_scaled_target();
+    }
+
+    fn allocate_scaled_target(&mut self) {
+        let factor = self.render_scale.factor.max(0.0).min(1.0);
+        // captured frames are read back from the offscreen target
+        if factor >= 1.0 && self.capture.is_none() {
+            self.scaled_target = None;
+            return;
+        }
+        let width = ((self.resolution.0 * factor) as u32).max(1);
+        let height = ((self.resolution.1 * factor) as u32).max(1);
+        // a plain rgba texture, the programs already output sRGB
+        self.scaled_target = Some(
+            glium::texture::Texture2d::empty_with_format(
+                &self.display,
+                glium::texture::UncompressedFloatFormat::U8U8U8U8,
+                glium::texture::MipmapsOption::NoMipmap,
+                width,
+                height,
+            )
+            .unwrap(),
+        );
+    }
+
+    /// Switches between (borderless) fullscreen and a decorated window of the given logical size.
+    pub fn set_fullscreen(&mut self, fullscreen: bool, windowed_size: (u32, u32)) {
+        if fullscreen == self.fullscreen {
+            return;
+        }
+        self.fullscreen = fullscreen;
+        let gl_window = self.display.gl_window();
+        let window = gl_window.window();
+        if fullscreen {
+            match &self.placement {
+                Placement::Fullscreen(monitor_handle) => {
+                    window.set_fullscreen(Some(glutin::window::Fullscreen::Borderless(Some(
+                        monitor_handle.clone(),
+                    ))));
+                }
+                Placement::Spanning { position, size } => {
+                    window.set_decorations(false);
+                    window.set_outer_position(*position);
+                    window.set_inner_size(*size);
+                }
+            }
+        } else {
+            window.set_fullscreen(None);
+            window.set_decorations(true);
+            window.set_inner_size(glutin::dpi::LogicalSize::new(
+                windowed_size.0,
+                windowed_size.1,
+            ));
+            window.set_outer_position(self.placement.position());
+        }
+        // the new resolution is reported through a resize event
+    }
+
+    fn set_gpu_timing(&mut self, enabled: bool) {
+        if !enabled {
+            self.gpu_timers = None;
+        } else if self.gpu_timers.is_none() && self.gpu_timing_supported {
+            self.gpu_timers = Some(GpuTimers::new());
+        }
+    }
+
+    /// Draws a frame and returns the time spent in each pass.
+    pub fn draw(&mut self, frame: &FrameParameters) -> DrawTimes {
+        self.set_gpu_timing(frame.gpu_timing);
+        let queries = match self.gpu_timers {
+            Some(_) => match PassQueries::new(&self.display, self.drawn_particles(frame) > 0) {
+                Ok(queries) => Some(queries),
+                Err(_) => {
+                    eprintln!("Timer queries are not supported, disabling gpu timing");
+                    self.gpu_timing_supported = false;
+                    self.gpu_timers = None;
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let image = glium::texture::RawImage2d::from_raw_rgba(frame.current_frame.clone(), (BARS, 1));
+        let rect = glium::Rect {
+            left: 0,
+            bottom: 0,
+            width: BARS,
+            height: 1,
+        };
+        self.spectrum_texture.write(rect, image);
+
+        // overlays are drawn at full resolution, unless frames are captured from the scaled target
+        let overlays_scaled = self.capture.is_some();
+        let overlay_size = match &self.scaled_target {
+            Some(scaled_target) if overlays_scaled => scaled_target.dimensions(),
+            _ => self.display.get_framebuffer_dimensions(),
+        };
+        let mut overlays = Overlays {
+            text: match &frame.text {
+                Some(text) => self.glyph_atlas.layout(text, overlay_size),
+                None => Vec::new(),
+            },
+            qr_code: match &frame.qr_code {
+                Some((qr_code, alpha)) if *alpha > 0.0 => vec![qr_code.quad(*alpha, overlay_size)],
+                _ => Vec::new(),
+            },
+            banner: frame.banner.iter().map(|banner| banner.background()).collect(),
+        };
+        for quad in overlays
+            .text
+            .iter_mut()
+            .chain(overlays.qr_code.iter_mut())
+            .chain(overlays.banner.iter_mut())
+        {
+            for channel in quad.color[..3].iter_mut() {
+                *channel *= frame.brightness;
+            }
+        }
+
+        let mut target = self.display.draw();
+        let mut pass_times = match &self.scaled_target {
+            Some(scaled_target) => {
+                let mut framebuffer =
+                    glium::framebuffer::SimpleFrameBuffer::new(&self.display, scaled_target)
+                        .unwrap();
+                let pass_times = self.draw_passes(&mut framebuffer, frame, queries.as_ref());
+                if overlays_scaled {
+                    self.draw_overlays(&mut framebuffer, &overlays);
+                }
+                framebuffer.fill(&target, self.render_scale.filter.into());
+                pass_times
+            }
+            None => self.draw_passes(&mut target, frame, queries.as_ref()),
+        };
+        if !overlays_scaled {
+            self.draw_overlays(&mut target, &overlays);
+        }
+        let start = Instant::now();
+        target.finish().unwrap();
+        pass_times.present = start.elapsed();
+
+        let gpu = match &mut self.gpu_timers {
+            Some(gpu_timers) => {
+                gpu_timers.push(queries.unwrap());
+                gpu_timers.collect()
+            }
+            None => Vec::new(),
+        };
+        DrawTimes {
+            cpu: pass_times,
+            gpu: gpu,
+        }
+    }
+
+    /// Saves the frame that was drawn last as the next png file, if frames are captured.
+    pub fn capture_frame(&mut self) -> Result<(), String> {
+        let (directory, index) = match &mut self.capture {
+            Some(capture) => capture,
+            None => return Ok(()),
+        };
+        let scaled_target = self.scaled_target.as_ref().unwrap();
+        let raw: glium::texture::RawImage2d<u8> = scaled_target.read();
+        let image = image::RgbaImage::from_raw(raw.width, raw.height, raw.data.into_owned())
+            .ok_or_else(|| "Could not read back the frame".to_string())?;
+        // OpenGL stores the rows from bottom to top
+        let image = image::imageops::flip_vertical(&image);
+        let path = directory.join(format!("{:06}.png", index));
+        image
+            .save(&path)
+            .map_err(|e| format!("Could not save {}: {}", path.display(), e))?;
+        *index += 1;
+        Ok(())
+    }
+
+    fn render_scale_factor(&self) -> f32 {
+        match &self.scaled_target {
+            Some(scaled_target) => scaled_target.height() as f32 / self.resolution.1,
+            None => 1.0,
+        }
+    }
+
+    // The number of particles drawn in the frame.
+    fn drawn_particles(&self, frame: &FrameParameters) -> usize {
+        (self.num_particles as f32 * frame.particle_fraction) as usize
+    }
+
+    fn draw_overlays<S: Surface>(&self, target: &mut S, overlays: &Overlays) {
+        if let Some(qr_texture) = &self.qr_texture {
+            self.overlay
+                .draw(&self.display, target, Some(qr_texture), true, &overlays.qr_code);
+        }
+        // the banner text is part of the text, so the strip is drawn before it
+        self.overlay
+            .draw(&self.display, target, None, false, &overlays.banner);
+        self.overlay.draw(
+            &self.display,
+            target,
+            Some(self.glyph_atlas.texture()),
+            false,
+            &overlays.text,
+        );
+    }
+
+    fn draw_passes<S: Surface>(
+        &self,
+        target: &mut S,
+        frame: &FrameParameters,
+        queries: Option<&PassQueries>,
+    ) -> PassTimes {
+        let mut pass_times = PassTimes::default();
+        let start = Instant::now();
+        target.clear_all((0.0, 0.0, 0.0, 1.0), 0.0, 0);
+
+        let uniforms = WithUserUniforms {
+            uniforms: background_uniforms(self.resolution, frame),
+            program: &self.programs.background,
+            user_uniforms: &frame.user_uniforms,
+            images: &self.images,
+            image_slots: &self.programs.image_slots,
+            image_transitions: &frame.image_transitions,
+        };
+        let draw_parameters = glium::DrawParameters {
+            time_elapsed_query: queries.map(|q| &q.background),
+            .. Default::default()
+        };
+        target
+            .draw(
+                &self.quad_v,
+                &self.quad_i,
+                &self.programs.background,
+                &uniforms,
+                &draw_parameters,
+            )
+            .unwrap();
+        pass_times.background = start.elapsed();
+
+        let uniforms = WithUserUniforms {
+            uniforms: particle_uniforms(self.resolution, frame),
+            program: &self.programs.particle,
+            user_uniforms: &frame.user_uniforms,
+            images: &self.images,
+            image_slots: &self.programs.image_slots,
+            image_transitions: &frame.image_transitions,
+        };
+        let draw_parameters = glium::DrawParameters {
+            blend: glium::Blend {
+                color: glium::BlendingFunction::Addition {
+                    source: glium::LinearBlendingFactor::One,
+                    destination: glium::LinearBlendingFactor::One,
+                },
+                alpha: glium::BlendingFunction::Addition {
+                    source: glium::LinearBlendingFactor::One,
+                    destination: glium::LinearBlendingFactor::One,
+                },
+                constant_value: (0.0, 0.0, 0.0, 0.0)
+            },
+            // keep the particles the same size on screen regardless of the render scale
+            point_size: Some(25.0 * self.render_scale_factor()),
+            time_elapsed_query: queries.and_then(|q| q.particles.as_ref()),
+            .. Default::default()
+        };
+        let start = Instant::now();
+        let num_particles = self.drawn_particles(frame);
+        if num_particles > 0 {
+            let particles = self.particle_buffer.slice(0..num_particles).unwrap();
+            target.draw(
+                (&self.particle_v, particles.per_instance().unwrap()),
+                &self.particle_i,
+                &self.programs.particle,
+                &uniforms,
+                &draw_parameters,
+            ).unwrap();
+        }
+        pass_times.particles = start.elapsed();
+
+        let start = Instant::now();
+        let uniforms = WithUserUniforms {
+            uniforms: foreground_uniforms(
+                self.resolution,
+                frame,
+                &self.logo_texture,
+                self.logo_alpha,
+                &self.spectrum_texture,
+            ),
+            program: &self.programs.foreground,
+            user_uniforms: &frame.user_uniforms,
+            images: &self.images,
+            image_slots: &self.programs.image_slots,
+            image_transitions: &frame.image_transitions,
+        };
+        let draw_parameters = glium::DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            time_elapsed_query: queries.map(|q| &q.foreground),
+            .. Default::default()
+        };
+        target
+            .draw(
+                &self.quad_v,
+                &self.quad_i,
+                &self.programs.foreground,
+                &uniforms,
+                &draw_parameters,
+            )
+            .unwrap();
+        if frame.brightness < 1.0 {
+            let uniforms = dim_uniforms(self.resolution, frame);
+            let draw_parameters = glium::DrawParameters {
+                blend: glium::Blend::alpha_blending(),
+                .. Default::default()
+            };
+            target
+                .draw(
+                    &self.quad_v,
+                    &self.quad_i,
+                    &self.programs.dim,
+                    &uniforms,
+                    &draw_parameters,
+                )
+                .unwrap();
+        }
+        pass_times.foreground = start.elapsed();
+        pass_times
+    }
+}
+
+// A fully transparent logo is left out together with the button it is drawn on.
+fn logo_alpha(logo: &RgbaImage) -> f32 {
+    if logo.pixels().all(|pixel| pixel[3] == 0) {
+        0.0
+    } else {
+        1.0
+    }
+}
+
+fn spawn_particles(variant: &str, resolution: (f32, f32), num_particles: u32) -> Vec<Particle> {
+    let mut particles = Vec::new();
+    for _ in 0..num_particles {
+        let (x, y, z) = if variant == "Circle" {
+            let phi = rand::random::<f32>() * 2.0 * std::f32::consts::PI;
+            let radius_diff = rand::random::<f32>();
+            let resolution_correction = resolution.1 / resolution.0;
+            let x = phi.cos() * (0.6 + radius_diff * 0.2) * resolution_correction;
+            let y = phi.sin() * (0.6 + radius_diff * 0.2);
+            let z = PARTICLE_SPAWN_Z * rand::random::<f32>();
+            (x, y, z)
+        } else if variant == "SnowyCircle" {
+            let x = rand::random::<f32>() * 2. - 1.;
+            let y = rand::random::<f32>() * 4. - 2.;
+            let z = rand::random::<f32>() * 2.;
+            (x, y, z)
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+        let speed = 0.3 * (rand::random::<f32>() * 0.75 + 0.3);
+
+        particles.push(Particle {
+            translation: [x, y],
+            start_z: z,
+            speed: speed,
+        });
+    }
+    particles
+}
@@ -0,0 +1,201 @@
+use glium::backend::Facade;
+use glium::texture::Texture2d;
+use glium::Surface;
+
+const VERTEX_SHADER: &str = "#version 300 es
+precision mediump float;
+
+in vec2 position;
+in vec2 tex_coords;
+in vec4 color;
+
+out vec2 uv;
+out vec4 tint;
+
+void main() {
+	uv = tex_coords;
+	tint = color;
+	gl_Position = vec4(position, 0, 1);
+}
+";
+
+const FRAGMENT_SHADER: &str = "#version 300 es
+precision mediump float;
+
+in vec2 uv;
+in vec4 tint;
+
+uniform sampler2D image;
+
+out vec4 fragColor;
+
+void main() {
+	fragColor = tint * texture(image, uv);
+}
+";
+
+/// A textured rectangle drawn over the visualization.
+/// Coordinates are fractions of the output, with the origin in the top left corner.
+#[derive(Clone, Copy, Debug)]
+pub struct Quad {
+    /// left, top, right and bottom edge
+    pub rect: [f32; 4],
+    /// the part of the texture that is shown, with the first row of the image at the top
+    pub tex_rect: [f32; 4],
+    /// multiplies the texture, straight alpha
+    pub color: [f32; 4],
+}
+
+impl Quad {
+    /// Cuts off the parts of the quad left of `left` and right of `right`,
+    /// None if nothing remains.
+    pub fn clip_horizontally(mut self, left: f32, right: f32) -> Option<Quad> {
+        let [x0, _, x1, _] = self.rect;
+        if x1 <= left || x0 >= right {
+            return None;
+        }
+        let tex_width = self.tex_rect[2] - self.tex_rect[0];
+        if x0 < left {
+            self.tex_rect[0] += tex_width * (left - x0) / (x1 - x0);
+            self.rect[0] = left;
+        }
+        if x1 > right {
+            self.tex_rect[2] -= tex_width * (x1 - right) / (x1 - x0);
+            self.rect[2] = right;
+        }
+        Some(self)
+    }
+}
+
+#[derive(Copy, Clone)]
+struct OverlayVertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+implement_vertex!(OverlayVertex, position, tex_coords, color);
+
+/// Draws batches of quads over the frame, shared by text, images and banners.
+pub struct OverlayRenderer {
+    program: glium::Program,
+    // stands in for the texture of solid quads
+    white: Texture2d,
+}
+
+impl OverlayRenderer {
+    pub fn new<F: Facade>(facade: &F) -> Result<OverlayRenderer, String> {
+        let program = glium::Program::new(
+            facade,
+            glium::program::ProgramCreationInput::SourceCode {
+                vertex_shader: VERTEX_SHADER,
+                tessellation_control_shader: None,
+                tessellation_evaluation_shader: None,
+                geometry_shader: None,
+                fragment_shader: FRAGMENT_SHADER,
+                transform_feedback_varyings: None,
+                outputs_srgb: true,
+                uses_point_size: false,
+            },
+        )
+        .map_err(|e| format!("overlay: {}", e))?;
+        let white = glium::texture::RawImage2d::from_raw_rgba(vec![255u8; 4], (1, 1));
+        Ok(OverlayRenderer {
+            program: program,
+            white: Texture2d::new(facade, white).unwrap(),
+        })
+    }
+
+    /// Draws the quads with the given texture, or as solid rectangles if there is none.
+    /// Pixelated textures are magnified without filtering, to keep their edges sharp.
+    pub fn draw<F: Facade, S: Surface>(
+        &self,
+        facade: &F,
+        target: &mut S,
+        texture: Option<&Texture2d>,
+        pixelated: bool,
+        quads: &[Quad],
+    ) {
+        if quads.is_empty() {
+            return;
+        }
+        let mut vertices = Vec::with_capacity(quads.len() * 6);
+        for quad in quads {
+            let [left, top, right, bottom] = quad.rect;
+            let [u0, v0, u1, v1] = quad.tex_rect;
+            let vertex = |x: f32, y: f32, u: f32, v: f32| OverlayVertex {
+                position: [x * 2.0 - 1.0, 1.0 - y * 2.0],
+                tex_coords: [u, v],
+                color: quad.color,
+            };
+            vertices.extend_from_slice(&[
+                vertex(left, top, u0, v0),
+                vertex(right, top, u1, v0),
+                vertex(left, bottom, u0, v1),
+                vertex(right, top, u1, v0),
+                vertex(right, bottom, u1, v1),
+                vertex(left, bottom, u0, v1),
+            ]);
+        }
+        let vertex_buffer = glium::VertexBuffer::new(facade, &vertices).unwrap();
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+        let magnify_filter = if pixelated {
+            glium::uniforms::MagnifySamplerFilter::Nearest
+        } else {
+            glium::uniforms::MagnifySamplerFilter::Linear
+        };
+        let uniforms = uniform! {
+            image: texture
+                .unwrap_or(&self.white)
+                .sampled()
+                .minify_filter(glium::uniforms::MinifySamplerFilter::Linear)
+                .magnify_filter(magnify_filter),
+        };
+        let draw_parameters = glium::DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            ..Default::default()
+        };
+        target
+            .draw(&vertex_buffer, &indices, &self.program, &uniforms, &draw_parameters)
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quad() -> Quad {
+        Quad {
+            rect: [0.2, 0.1, 0.6, 0.3],
+            tex_rect: [0.0, 0.0, 0.4, 1.0],
+            color: [1.0; 4],
+        }
+    }
+
+    fn assert_close(actual: [f32; 4], expected: [f32; 4]) {
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn keeps_quads_inside_the_bounds() {
+        let clipped = quad().clip_horizontally(0.0, 1.0).unwrap();
+        assert_eq!(clipped.rect, quad().rect);
+        assert_eq!(clipped.tex_rect, quad().tex_rect);
+    }
+
+    #[test]
+    fn drops_quads_outside_the_bounds() {
+        assert!(quad().clip_horizontally(0.6, 1.0).is_none());
+        assert!(quad().clip_horizontally(0.0, 0.2).is_none());
+    }
+
+    #[test]
+    fn clips_texture_with_the_rectangle() {
+        let clipped = quad().clip_horizontally(0.3, 0.5).unwrap();
+        assert_close(clipped.rect, [0.3, 0.1, 0.5, 0.3]);
+        assert_close(clipped.tex_rect, [0.1, 0.0, 0.3, 1.0]);
+    }
+}
@@ -0,0 +1,135 @@
+use glium::glutin;
+use glutin::platform::unix::WindowExtUnix;
+use glutin::platform::ContextTraitExt;
+use std::os::raw::{c_int, c_ulong, c_void};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// wake up this early before the next frame and spin for the rest,
+// since the os scheduler is not precise enough for smooth frame times
+const SPIN_MARGIN: Duration = Duration::from_millis(2);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FramePacing {
+    /// render as fast as the display refreshes
+    Vsync,
+    /// render at the requested ups without vsync, using a precise sleep
+    Timer,
+    /// render as fast as possible, for benchmarking
+    Uncapped,
+    /// like vsync, but late frames are shown immediately (with tearing) instead of
+    /// waiting for the next refresh. Falls back to vsync if the driver does not support it.
+    AdaptiveVsync,
+}
+
+impl FromStr for FramePacing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vsync" => Ok(FramePacing::Vsync),
+            "timer" => Ok(FramePacing::Timer),
+            "uncapped" => Ok(FramePacing::Uncapped),
+            "adaptive_vsync" => Ok(FramePacing::AdaptiveVsync),
+            _ => Err(format!("Unknown frame pacing: {}", s)),
+        }
+    }
+}
+
+impl Default for FramePacing {
+    fn default() -> Self {
+        FramePacing::Vsync
+    }
+}
+
+impl FramePacing {
+    pub fn uses_vsync(&self) -> bool {
+        match self {
+            FramePacing::Vsync | FramePacing::AdaptiveVsync => true,
+            FramePacing::Timer | FramePacing::Uncapped => false,
+        }
+    }
+}
+
+/// Decides when the next frame is drawn.
+pub struct FramePacer {
+    pacing: FramePacing,
+    interval: Duration,
+    next_frame: Instant,
+}
+
+impl FramePacer {
+    pub fn new(pacing: FramePacing, ups: f32) -> FramePacer {
+        FramePacer {
+            pacing: pacing,
+            interval: Duration::from_secs_f32(1.0 / ups),
+            next_frame: Instant::now(),
+        }
+    }
+
+    pub fn set_ups(&mut self, ups: f32) {
+        self.interval = Duration::from_secs_f32(1.0 / ups);
+    }
+
+    pub fn pacing(&self) -> FramePacing {
+        self.pacing
+    }
+
+    /// Called when the event loop woke up. Returns whether a frame should be drawn now.
+    /// For timer pacing, this spins until the exact time of the frame.
+    pub fn frame_due(&mut self) -> bool {
+        if self.pacing != FramePacing::Timer {
+            // vsync blocks in the buffer swap, uncapped does not wait at all
+            return true;
+        }
+        let now = Instant::now();
+        if now + SPIN_MARGIN < self.next_frame {
+            // woken up early by some event
+            return false;
+        }
+        while Instant::now() < self.next_frame {
+            std::hint::spin_loop();
+        }
+        // schedule relative to the planned time so the frame rate does not drift,
+        // unless we fell behind more than a frame
+        self.next_frame += self.interval;
+        if self.next_frame < Instant::now() {
+            self.next_frame = Instant::now() + self.interval;
+        }
+        true
+    }
+
+    pub fn control_flow(&self) -> glutin::event_loop::ControlFlow {
+        match self.pacing {
+            FramePacing::Timer => {
+                glutin::event_loop::ControlFlow::WaitUntil(self.next_frame - SPIN_MARGIN)
+            }
+            _ => glutin::event_loop::ControlFlow::Poll,
+        }
+    }
+}
+
+type SwapIntervalExt = unsafe extern "C" fn(*mut c_void, c_ulong, c_int);
+
+/// Sets a negative swap interval, which enables late swap tearing.
+/// Only supported for GLX contexts with GLX_EXT_swap_control_tear.
+pub fn enable_adaptive_vsync(display: &glium::Display) -> Result<(), String> {
+    let gl_window = display.gl_window();
+    if unsafe { gl_window.context().get_egl_display() }.is_some() {
+        return Err("Adaptive vsync is not supported with EGL".to_string());
+    }
+    let window = gl_window.window();
+    let (xlib_display, xlib_window) = match (window.xlib_display(), window.xlib_window()) {
+        (Some(display), Some(window)) => (display, window),
+        _ => return Err("Adaptive vsync requires X11".to_string()),
+    };
+    let swap_interval = gl_window.get_proc_address("glXSwapIntervalEXT");
+    if swap_interval.is_null() {
+        return Err("glXSwapIntervalEXT is not available".to_string());
+    }
+    unsafe {
+        let swap_interval: SwapIntervalExt = std::mem::transmute(swap_interval);
+        swap_interval(xlib_display, xlib_window, -1);
+    }
+    Ok(())
+}
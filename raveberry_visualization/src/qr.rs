@@ -0,0 +1,132 @@
+use crate::overlay::Quad;
+use image::RgbaImage;
+use std::str::FromStr;
+
+/// Seconds the code takes to fade in and out.
+const FADE_DURATION: f32 = 1.0;
+// light modules around the code, required by scanners
+const QUIET_ZONE: u32 = 2;
+
+/// The corner of the output the code is shown in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl FromStr for Corner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top_left" => Ok(Corner::TopLeft),
+            "top_right" => Ok(Corner::TopRight),
+            "bottom_left" => Ok(Corner::BottomLeft),
+            "bottom_right" => Ok(Corner::BottomRight),
+            _ => Err(format!(
+                "Unknown corner {}, expected top_left, top_right, bottom_left or bottom_right",
+                s
+            )),
+        }
+    }
+}
+
+/// A QR code shown in a corner of the visualization, e.g. to let guests open raveberry.
+#[derive(Clone, Debug)]
+pub struct QrOverlay {
+    pub url: String,
+    /// one pixel per module, including the quiet zone
+    pub image: RgbaImage,
+    pub corner: Corner,
+    /// the side length as a fraction of the output height
+    pub size: f32,
+    /// seconds the code is shown before it fades out
+    pub visible: f32,
+    /// seconds the code stays hidden before it fades in again, 0 to always show it
+    pub hidden: f32,
+}
+
+impl QrOverlay {
+    pub fn new(
+        url: &str,
+        corner: Corner,
+        size: f32,
+        visible: f32,
+        hidden: f32,
+    ) -> Result<QrOverlay, String> {
+        if !(size > 0.0 && size <= 1.0) {
+            return Err(format!("size must be between 0 and 1, got {}", size));
+        }
+        if !(visible > 0.0) || !(hidden >= 0.0) {
+            return Err("visible must be positive and hidden must not be negative".to_string());
+        }
+        Ok(QrOverlay {
+            url: url.to_string(),
+            image: encode(url)?,
+            corner: corner,
+            size: size,
+            visible: visible,
+            hidden: hidden,
+        })
+    }
+
+    /// The opacity of the code, `seconds` after it was set.
+    pub fn alpha(&self, seconds: f32) -> f32 {
+        let fade_in = (seconds / FADE_DURATION).min(1.0);
+        if self.hidden <= 0.0 {
+            return fade_in;
+        }
+        let time = seconds % (self.visible + self.hidden);
+        if time >= self.visible {
+            return 0.0;
+        }
+        let fade_in = (time / FADE_DURATION).min(1.0);
+        let fade_out = ((self.visible - time) / FADE_DURATION).min(1.0);
+        fade_in.min(fade_out)
+    }
+
+    /// The quad showing the code on an output of the given size in pixels.
+    pub fn quad(&self, alpha: f32, output_size: (u32, u32)) -> Quad {
+        let (width, height) = (output_size.0 as f32, output_size.1 as f32);
+        // keep the code square and away from the edges
+        let size = (self.size * height / width, self.size);
+        let margin = (0.03 * height / width, 0.03);
+        let left = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => margin.0,
+            Corner::TopRight | Corner::BottomRight => 1.0 - margin.0 - size.0,
+        };
+        let top = match self.corner {
+            Corner::TopLeft | Corner::TopRight => margin.1,
+            Corner::BottomLeft | Corner::BottomRight => 1.0 - margin.1 - size.1,
+        };
+        Quad {
+            rect: [left, top, left + size.0, top + size.1],
+            tex_rect: [0.0, 0.0, 1.0, 1.0],
+            color: [1.0, 1.0, 1.0, alpha],
+        }
+    }
+}
+
+/// Encodes the url as a black on white QR code with one pixel per module.
+fn encode(url: &str) -> Result<RgbaImage, String> {
+    let code = qrcode::QrCode::new(url.as_bytes())
+        .map_err(|e| format!("Could not encode {}: {}", url, e))?;
+    let modules = code.width() as u32;
+    let colors = code.to_colors();
+    let size = modules + 2 * QUIET_ZONE;
+    Ok(RgbaImage::from_fn(size, size, |x, y| {
+        let dark = x >= QUIET_ZONE
+            && y >= QUIET_ZONE
+            && x < QUIET_ZONE + modules
+            && y < QUIET_ZONE + modules
+            && colors[((y - QUIET_ZONE) * modules + x - QUIET_ZONE) as usize]
+                == qrcode::Color::Dark;
+        if dark {
+            image::Rgba([0, 0, 0, 255])
+        } else {
+            image::Rgba([255, 255, 255, 255])
+        }
+    }))
+}
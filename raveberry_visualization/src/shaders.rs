@@ -0,0 +1,102 @@
+use crate::uniforms;
+use crate::validation::{self, Issue};
+use crate::variants::Variant;
+use glium::backend::Facade;
+use glium::uniforms::UniformType;
+
+// Covers the frame with black, so the brightness can be changed regardless of the variant.
+const DIM_SHADER: &str = "#version 300 es
+precision mediump float;
+
+uniform float brightness;
+
+out vec4 fragColor;
+
+void main() {
+	fragColor = vec4(0, 0, 0, 1. - brightness);
+}
+";
+
+/// All programs needed to draw a variant.
+pub struct Programs {
+    pub background: glium::Program,
+    pub foreground: glium::Program,
+    pub particle: glium::Program,
+    // darkens the frame if the brightness is reduced
+    pub dim: glium::Program,
+    /// the image slots listed in the manifest of the variant
+    pub image_slots: Vec<String>,
+}
+
+fn quad_program<F: Facade>(
+    facade: &F,
+    quad_vs: &str,
+    fragment_shader: &str,
+    file: &str,
+) -> Result<glium::Program, String> {
+    // specify outputs_srgb in every shader for correct color space output
+    // https://github.com/rust-windowing/glutin/issues/1175
+    glium::Program::new(
+        facade,
+        glium::program::ProgramCreationInput::SourceCode {
+            vertex_shader: quad_vs,
+            tessellation_control_shader: None,
+            tessellation_evaluation_shader: None,
+            geometry_shader: None,
+            fragment_shader: fragment_shader,
+            transform_feedback_varyings: None,
+            outputs_srgb: true,
+            uses_point_size: false,
+        },
+    )
+    .map_err(|e| format!("{}: {}", file, e))
+}
+
+impl Programs {
+    /// Reads, compiles and links the shaders of a variant.
+    /// The error contains the GLSL log of the shader that failed.
+    pub fn compile<F: Facade>(facade: &F, variant: &Variant) -> Result<Programs, String> {
+        let quad_vs = variant.read_shader("quad.vs")?;
+        let background_fs = variant.read_shader("background.fs")?;
+        let foreground_fs = variant.read_shader("foreground.fs")?;
+        let particle_vs = variant.read_shader("particle.vs")?;
+        let particle_fs = variant.read_shader("particle.fs")?;
+
+        Ok(Programs {
+            background: quad_program(facade, &quad_vs, &background_fs, "background.fs")?,
+            foreground: quad_program(facade, &quad_vs, &foreground_fs, "foreground.fs")?,
+            particle: glium::Program::from_source(facade, &particle_vs, &particle_fs, None)
+                .map_err(|e| format!("particle.vs/particle.fs: {}", e))?,
+            dim: quad_program(facade, &quad_vs, DIM_SHADER, "quad.vs")?,
+            image_slots: variant.image_slots.clone(),
+        })
+    }
+
+    /// The uniforms declared by the programs that are not supplied by the visualization,
+    /// with their GLSL type.
+    pub fn user_uniforms(&self) -> Vec<(String, UniformType)> {
+        let mut declared: Vec<(String, UniformType)> = Vec::new();
+        for program in [&self.background, &self.particle, &self.foreground].iter() {
+            for (name, ty) in uniforms::declared_user_uniforms(program, &self.image_slots) {
+                if declared.iter().all(|(n, _)| n != &name) {
+                    declared.push((name, ty));
+                }
+            }
+        }
+        declared.sort_by(|(a, _), (b, _)| a.cmp(b));
+        declared
+    }
+
+    /// Compares the interface of the programs with what the passes supply.
+    pub fn validate(&self, user_uniforms: &[String]) -> Vec<Issue> {
+        validation::check(
+            &[
+                (&validation::background(), &self.background),
+                (&validation::particles(), &self.particle),
+                (&validation::foreground(), &self.foreground),
+            ],
+            user_uniforms,
+            &self.image_slots,
+        )
+    }
+}
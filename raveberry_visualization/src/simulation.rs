@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+// The intensity dependent animations were tuned for 30 updates per second,
+// so the simulation keeps running at that rate regardless of the frame rate.
+pub const SIMULATION_RATE: f32 = 30.0;
+
+/// Accumulates the intensity of the spectrum in fixed time steps,
+/// so animations behave the same no matter how fast frames are rendered.
+pub struct Simulation {
+    step: Duration,
+    simulated: Duration,
+    steps: u64,
+    total_intensity: f32,
+    current_intensity: f32,
+}
+
+impl Simulation {
+    pub fn new() -> Simulation {
+        Simulation {
+            step: Duration::from_secs_f32(1.0 / SIMULATION_RATE),
+            simulated: Duration::new(0, 0),
+            steps: 0,
+            total_intensity: 0.0,
+            current_intensity: 0.0,
+        }
+    }
+
+    /// Runs all steps up to the given time, using the latest intensity for each of them.
+    pub fn advance_to(&mut self, time: Duration, current_intensity: f32) {
+        self.current_intensity = current_intensity;
+        while self.simulated + self.step <= time {
+            self.total_intensity += current_intensity;
+            self.simulated += self.step;
+            self.steps += 1;
+        }
+    }
+
+    /// The accumulated intensity, interpolated between the last step and the next one
+    /// so animations stay smooth when rendering faster than the simulation rate.
+    pub fn total_intensity(&self, time: Duration) -> f32 {
+        let partial_step = time.saturating_sub(self.simulated).as_secs_f32() / self.step.as_secs_f32();
+        self.total_intensity + self.current_intensity * partial_step.min(1.0)
+    }
+
+    /// The fraction of time the spectrum was intense.
+    pub fn intensity_fraction(&self) -> f32 {
+        if self.steps == 0 {
+            return 0.0;
+        }
+        self.total_intensity / self.steps as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(seconds: f32) -> Duration {
+        Duration::from_secs_f32(seconds)
+    }
+
+    #[test]
+    fn runs_whole_steps_only() {
+        let mut simulation = Simulation::new();
+        simulation.advance_to(seconds(2.5 / SIMULATION_RATE), 1.0);
+        assert_eq!(simulation.steps, 2);
+        assert_eq!(simulation.total_intensity, 2.0);
+    }
+
+    #[test]
+    fn independent_of_frame_rate() {
+        let mut slow = Simulation::new();
+        let mut fast = Simulation::new();
+        for frame in 1..=10 {
+            slow.advance_to(seconds(frame as f32 / 10.0), 0.5);
+        }
+        for frame in 1..=100 {
+            fast.advance_to(seconds(frame as f32 / 100.0), 0.5);
+        }
+        assert_eq!(slow.steps, fast.steps);
+        let difference = slow.total_intensity(seconds(1.0)) - fast.total_intensity(seconds(1.0));
+        assert!(difference.abs() < 1e-3);
+    }
+
+    #[test]
+    fn interpolates_between_steps() {
+        let mut simulation = Simulation::new();
+        let time = seconds(1.5 / SIMULATION_RATE);
+        simulation.advance_to(time, 1.0);
+        assert!((simulation.total_intensity(time) - 1.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn intensity_fraction() {
+        let mut simulation = Simulation::new();
+        assert_eq!(simulation.intensity_fraction(), 0.0);
+        simulation.advance_to(seconds(2.0 / SIMULATION_RATE), 1.0);
+        simulation.advance_to(seconds(4.0 / SIMULATION_RATE), 0.0);
+        assert!((simulation.intensity_fraction() - 0.5).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,282 @@
+use pyo3::prelude::*;
+use std::ops::AddAssign;
+use std::time::{Duration, Instant};
+
+/// Time spent in each stage of drawing a frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PassTimes {
+    pub background: Duration,
+    pub particles: Duration,
+    pub foreground: Duration,
+    /// finishing the frame, including the buffer swap
+    pub present: Duration,
+}
+
+impl AddAssign for PassTimes {
+    fn add_assign(&mut self, other: PassTimes) {
+        self.background += other.background;
+        self.particles += other.particles;
+        self.foreground += other.foreground;
+        self.present += other.present;
+    }
+}
+
+/// Frame timing statistics over the last fps measurement window, as reported to python.
+/// All times are in milliseconds, per-frame values are averages over the window.
+/// Pass times are averaged over the outputs, so they do not grow with the number of monitors.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct Stats {
+    #[pyo3(get)]
+    pub fps: f32,
+    #[pyo3(get)]
+    pub frames: u32,
+    #[pyo3(get)]
+    pub frame_time_p50: f32,
+    #[pyo3(get)]
+    pub frame_time_p95: f32,
+    #[pyo3(get)]
+    pub frame_time_p99: f32,
+    #[pyo3(get)]
+    pub frame_time_max: f32,
+    /// frames that took more than 1.5 times the target frame time
+    #[pyo3(get)]
+    pub late_frames: u32,
+    /// frames that should have been shown while a late frame was still being drawn
+    #[pyo3(get)]
+    pub dropped_frames: u32,
+    #[pyo3(get)]
+    pub smoothing_time: f32,
+    #[pyo3(get)]
+    pub background_pass_time: f32,
+    #[pyo3(get)]
+    pub particle_pass_time: f32,
+    #[pyo3(get)]
+    pub foreground_pass_time: f32,
+    #[pyo3(get)]
+    pub present_time: f32,
+    /// measured with timer queries, None if gpu timing is disabled or not supported
+    #[pyo3(get)]
+    pub gpu_background_pass_time: Option<f32>,
+    #[pyo3(get)]
+    pub gpu_particle_pass_time: Option<f32>,
+    #[pyo3(get)]
+    pub gpu_foreground_pass_time: Option<f32>,
+    /// age of the spectrum at the time it was drawn
+    #[pyo3(get)]
+    pub input_staleness: f32,
+    #[pyo3(get)]
+    pub input_staleness_max: f32,
+}
+
+#[pymethods]
+impl Stats {
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+fn millis(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+/// Gathers measurements for every frame until the window is finished.
+pub struct StatsCollector {
+    last_frame: Option<Instant>,
+    frame_times: Vec<f32>,
+    late_frames: u32,
+    dropped_frames: u32,
+    smoothing_time: Duration,
+    pass_times: PassTimes,
+    // the number of times an output was drawn, once per output and frame
+    output_frames: u32,
+    gpu_pass_times: PassTimes,
+    gpu_frames: u32,
+    input_staleness: Duration,
+    input_staleness_max: Duration,
+}
+
+impl StatsCollector {
+    pub fn new() -> StatsCollector {
+        StatsCollector {
+            last_frame: None,
+            frame_times: Vec::new(),
+            late_frames: 0,
+            dropped_frames: 0,
+            smoothing_time: Duration::new(0, 0),
+            pass_times: PassTimes::default(),
+            output_frames: 0,
+            gpu_pass_times: PassTimes::default(),
+            gpu_frames: 0,
+            input_staleness: Duration::new(0, 0),
+            input_staleness_max: Duration::new(0, 0),
+        }
+    }
+
+    /// Called after a frame was presented, `target_frame_time` being the time a frame should take.
+    pub fn frame_finished(&mut self, now: Instant, target_frame_time: Duration) {
+        if let Some(last_frame) = self.last_frame {
+            let frame_time = now.duration_since(last_frame);
+            self.frame_times.push(millis(frame_time));
+            let ratio = frame_time.as_secs_f32() / target_frame_time.as_secs_f32();
+            if ratio > 1.5 {
+                self.late_frames += 1;
+                // every full frame interval beyond the first one is a frame that was not shown
+                self.dropped_frames += (ratio.round() as u32).saturating_sub(1);
+            }
+        }
+        self.last_frame = Some(now);
+    }
+
+    pub fn add_smoothing_time(&mut self, duration: Duration) {
+        self.smoothing_time += duration;
+    }
+
+    /// Called for every output a frame was drawn to.
+    pub fn add_pass_times(&mut self, pass_times: PassTimes) {
+        self.pass_times += pass_times;
+        self.output_frames += 1;
+    }
+
+    pub fn add_gpu_pass_times(&mut self, pass_times: PassTimes) {
+        self.gpu_pass_times += pass_times;
+        self.gpu_frames += 1;
+    }
+
+    pub fn add_input_staleness(&mut self, staleness: Duration) {
+        self.input_staleness += staleness;
+        self.input_staleness_max = self.input_staleness_max.max(staleness);
+    }
+
+    /// Summarizes the current window and starts a new one.
+    pub fn finish_window(&mut self, fps: f32) -> Stats {
+        let mut frame_times = std::mem::replace(&mut self.frame_times, Vec::new());
+        frame_times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let percentile = |p: f32| -> f32 {
+            if frame_times.is_empty() {
+                return 0.0;
+            }
+            let index = (p / 100.0 * (frame_times.len() - 1) as f32).round() as usize;
+            frame_times[index]
+        };
+        let frames = frame_times.len().max(1) as f32;
+        let per_frame = |duration: Duration| millis(duration) / frames;
+        let output_frames = self.output_frames.max(1) as f32;
+        let per_output_frame = |duration: Duration| millis(duration) / output_frames;
+        // gpu results arrive with a delay, so they are averaged over their own count
+        let gpu_frames = self.gpu_frames;
+        let per_gpu_frame = |duration: Duration| {
+            if gpu_frames == 0 {
+                None
+            } else {
+                Some(millis(duration) / gpu_frames as f32)
+            }
+        };
+
+        let stats = Stats {
+            fps: fps,
+            frames: frame_times.len() as u32,
+            frame_time_p50: percentile(50.0),
+            frame_time_p95: percentile(95.0),
+            frame_time_p99: percentile(99.0),
+            frame_time_max: frame_times.last().cloned().unwrap_or(0.0),
+            late_frames: self.late_frames,
+            dropped_frames: self.dropped_frames,
+            smoothing_time: per_frame(self.smoothing_time),
+            background_pass_time: per_output_frame(self.pass_times.background),
+            particle_pass_time: per_output_frame(self.pass_times.particles),
+            foreground_pass_time: per_output_frame(self.pass_times.foreground),
+            present_time: per_output_frame(self.pass_times.present),
+            gpu_background_pass_time: per_gpu_frame(self.gpu_pass_times.background),
+            gpu_particle_pass_time: per_gpu_frame(self.gpu_pass_times.particles),
+            gpu_foreground_pass_time: per_gpu_frame(self.gpu_pass_times.foreground),
+            input_staleness: per_frame(self.input_staleness),
+            input_staleness_max: millis(self.input_staleness_max),
+        };
+
+        // keep the time of the last frame so the next window measures its first frame correctly
+        let last_frame = self.last_frame;
+        *self = StatsCollector::new();
+        self.last_frame = last_frame;
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    fn collect_frame_times(frame_times: &[u64]) -> Stats {
+        let mut collector = StatsCollector::new();
+        let start = Instant::now();
+        let mut now = start;
+        collector.frame_finished(now, Duration::from_millis(10));
+        for frame_time in frame_times {
+            now += Duration::from_millis(*frame_time);
+            collector.frame_finished(now, Duration::from_millis(10));
+        }
+        collector.finish_window(0.0)
+    }
+
+    #[test]
+    fn percentiles_of_frame_times() {
+        let frame_times = (1..=100).collect::<Vec<_>>();
+        let stats = collect_frame_times(&frame_times);
+        assert_eq!(stats.frames, 100);
+        assert_close(stats.frame_time_p50, 51.0);
+        assert_close(stats.frame_time_p95, 95.0);
+        assert_close(stats.frame_time_p99, 99.0);
+        assert_close(stats.frame_time_max, 100.0);
+    }
+
+    #[test]
+    fn empty_window() {
+        let stats = StatsCollector::new().finish_window(0.0);
+        assert_eq!(stats.frames, 0);
+        assert_close(stats.frame_time_p50, 0.0);
+        assert_eq!(stats.gpu_background_pass_time, None);
+    }
+
+    #[test]
+    fn counts_late_and_dropped_frames() {
+        let stats = collect_frame_times(&[10, 14, 16, 30, 10]);
+        assert_eq!(stats.late_frames, 2);
+        assert_eq!(stats.dropped_frames, 3);
+    }
+
+    #[test]
+    fn pass_times_are_averaged_over_outputs() {
+        let mut collector = StatsCollector::new();
+        let pass_times = PassTimes {
+            background: Duration::from_millis(2),
+            ..PassTimes::default()
+        };
+        let start = Instant::now();
+        collector.frame_finished(start, Duration::from_millis(10));
+        for frame in 1..=2 {
+            // two mirrored outputs
+            collector.add_pass_times(pass_times);
+            collector.add_pass_times(pass_times);
+            let now = start + Duration::from_millis(10 * frame);
+            collector.frame_finished(now, Duration::from_millis(10));
+        }
+        let stats = collector.finish_window(0.0);
+        assert_close(stats.background_pass_time, 2.0);
+    }
+
+    #[test]
+    fn window_keeps_last_frame() {
+        let mut collector = StatsCollector::new();
+        let start = Instant::now();
+        collector.frame_finished(start, Duration::from_millis(10));
+        collector.finish_window(0.0);
+        collector.frame_finished(start + Duration::from_millis(10), Duration::from_millis(10));
+        let stats = collector.finish_window(0.0);
+        assert_eq!(stats.frames, 1);
+        assert_close(stats.frame_time_max, 10.0);
+    }
+}
@@ -0,0 +1,443 @@
+use crate::overlay::Quad;
+use ab_glyph::{Font, FontArc, GlyphId, PxScale, ScaleFont};
+use glium::backend::Facade;
+use glium::texture::Texture2d;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Fonts that are tried if no font was set, in this order.
+const DEFAULT_FONTS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+    "/usr/share/fonts/truetype/freefont/FreeSans.ttf",
+];
+
+const ATLAS_SIZE: u32 = 1024;
+// glyphs are rasterized at the size they are shown at, within these bounds
+const MIN_PIXEL_SIZE: f32 = 4.0;
+const MAX_PIXEL_SIZE: f32 = 256.0;
+
+/// Seconds a scrolling line stays at its start before it begins to scroll.
+const MARQUEE_DELAY: f32 = 2.0;
+/// Scrolling speed in line heights per second.
+const MARQUEE_SPEED: f32 = 2.0;
+/// Space between the end of a scrolling line and its repetition, in line heights.
+const MARQUEE_GAP: f32 = 2.0;
+
+/// Reads a TrueType or OpenType font.
+pub fn load_font(path: &Path) -> Result<FontArc, String> {
+    let data = fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    FontArc::try_from_vec(data).map_err(|e| format!("Could not load {}: {}", path.display(), e))
+}
+
+/// The first of the common system fonts that is installed.
+pub fn default_font() -> Result<FontArc, String> {
+    DEFAULT_FONTS
+        .iter()
+        .map(Path::new)
+        .find(|path| path.is_file())
+        .ok_or_else(|| "No font found, set one with set_font".to_string())
+        .and_then(load_font)
+}
+
+/// A block of text shown over the visualization, set through `Controller.set_text`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextOverlay {
+    /// lines are separated by newlines
+    pub text: String,
+    /// where the anchor is placed, as fractions of the output from the top left corner
+    pub position: (f32, f32),
+    /// the point of the text box that is placed at the position, (0, 0) is its top left corner,
+    /// (1, 1) its bottom right one. Also aligns the lines within the box.
+    pub anchor: (f32, f32),
+    /// the height of a line as a fraction of the output height
+    pub size: f32,
+    /// straight alpha
+    pub color: [f32; 4],
+    /// seconds it takes the text to fade in and out
+    pub fade: f32,
+    /// lines wider than this fraction of the output width scroll, unlimited if None
+    pub max_width: Option<f32>,
+}
+
+/// A text overlay that is shown or fading out, tracked by the render thread.
+pub struct ShownText {
+    pub name: String,
+    pub overlay: Arc<TextOverlay>,
+    pub shown_at: f32,
+    pub hidden_at: Option<f32>,
+}
+
+impl ShownText {
+    pub fn alpha(&self, seconds_elapsed: f32) -> f32 {
+        let fade = |since: f32| {
+            if self.overlay.fade > 0.0 {
+                (since / self.overlay.fade).max(0.0).min(1.0)
+            } else {
+                1.0
+            }
+        };
+        let alpha = fade(seconds_elapsed - self.shown_at);
+        match self.hidden_at {
+            Some(hidden_at) => alpha.min(1.0 - fade(seconds_elapsed - hidden_at)),
+            None => alpha,
+        }
+    }
+
+    pub fn faded_out(&self, seconds_elapsed: f32) -> bool {
+        self.hidden_at.is_some() && self.alpha(seconds_elapsed) <= 0.0
+    }
+}
+
+/// A text overlay as drawn in a single frame.
+#[derive(Clone, Debug)]
+pub struct TextDraw {
+    pub overlay: Arc<TextOverlay>,
+    pub alpha: f32,
+    /// seconds since the text appeared, determines the marquee position
+    pub scroll_time: f32,
+}
+
+/// All text drawn in a single frame.
+#[derive(Clone)]
+pub struct TextFrame {
+    /// changes whenever the font is replaced
+    pub font_id: usize,
+    pub font: FontArc,
+    pub texts: Vec<TextDraw>,
+}
+
+#[derive(Clone, Copy)]
+struct AtlasGlyph {
+    tex_rect: [f32; 4],
+    // offset of the top left corner from the glyph origin on the baseline, in pixels
+    offset: (f32, f32),
+    size: (f32, f32),
+}
+
+struct AtlasFull;
+
+// Where the copies of a line start horizontally. Lines that fit into the box are aligned
+// within it, wider lines scroll and are repeated after a gap so the marquee never runs empty.
+fn line_starts(
+    left: f32,
+    box_width: f32,
+    line_width: f32,
+    anchor: f32,
+    line_height: f32,
+    scroll_time: f32,
+) -> Vec<f32> {
+    if line_width > box_width {
+        let period = line_width + MARQUEE_GAP * line_height;
+        let scrolled = (scroll_time - MARQUEE_DELAY).max(0.0) * MARQUEE_SPEED * line_height;
+        let offset = scrolled % period;
+        vec![left - offset, left - offset + period]
+    } else {
+        // align the line within the box like the box is aligned to its position
+        vec![left + anchor * (box_width - line_width)]
+    }
+}
+
+/// Rasterizes glyphs on demand into a texture shared by all text of an output.
+pub struct GlyphAtlas {
+    texture: Texture2d,
+    font_id: Option<usize>,
+    // None for glyphs without outline, like spaces
+    glyphs: HashMap<(GlyphId, u32), Option<AtlasGlyph>>,
+    // where the next glyph is placed and the height of the current row
+    cursor: (u32, u32),
+    row_height: u32,
+}
+
+impl GlyphAtlas {
+    pub fn new<F: Facade>(facade: &F) -> GlyphAtlas {
+        GlyphAtlas {
+            texture: Texture2d::empty(facade, ATLAS_SIZE, ATLAS_SIZE).unwrap(),
+            font_id: None,
+            glyphs: HashMap::new(),
+            cursor: (0, 0),
+            row_height: 0,
+        }
+    }
+
+    pub fn texture(&self) -> &Texture2d {
+        &self.texture
+    }
+
+    // Forgets all glyphs, they are overwritten as new ones are added.
+    fn clear(&mut self) {
+        self.glyphs.clear();
+        self.cursor = (0, 0);
+        self.row_height = 0;
+    }
+
+    fn glyph(
+        &mut self,
+        font: &FontArc,
+        id: GlyphId,
+        pixel_size: f32,
+    ) -> Result<Option<AtlasGlyph>, AtlasFull> {
+        let key = (id, pixel_size as u32);
+        if let Some(glyph) = self.glyphs.get(&key) {
+            return Ok(*glyph);
+        }
+        let outlined = match font.outline_glyph(id.with_scale(PxScale::from(pixel_size))) {
+            Some(outlined) => outlined,
+            None => {
+                self.glyphs.insert(key, None);
+                return Ok(None);
+            }
+        };
+        let bounds = outlined.px_bounds();
+        let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+        if width == 0 || height == 0 {
+            self.glyphs.insert(key, None);
+            return Ok(None);
+        }
+        // leave a pixel between glyphs so they do not bleed into each other when filtered
+        if self.cursor.0 + width + 1 > ATLAS_SIZE {
+            self.cursor = (0, self.cursor.1 + self.row_height + 1);
+            self.row_height = 0;
+        }
+        if self.cursor.1 + height + 1 > ATLAS_SIZE || width + 1 > ATLAS_SIZE {
+            return Err(AtlasFull);
+        }
+        let mut pixels = vec![255u8; (width * height * 4) as usize];
+        for alpha in pixels.iter_mut().skip(3).step_by(4) {
+            *alpha = 0;
+        }
+        outlined.draw(|x, y, coverage| {
+            if x < width && y < height {
+                pixels[((y * width + x) * 4 + 3) as usize] = (coverage * 255.0) as u8;
+            }
+        });
+        let (left, bottom) = self.cursor;
+        self.texture.write(
+            glium::Rect {
+                left: left,
+                bottom: bottom,
+                width: width,
+                height: height,
+            },
+            glium::texture::RawImage2d::from_raw_rgba(pixels, (width, height)),
+        );
+        self.cursor.0 += width + 1;
+        self.row_height = self.row_height.max(height);
+
+        let size = ATLAS_SIZE as f32;
+        let glyph = AtlasGlyph {
+            // the first row of the glyph was written to the bottom of the rectangle
+            tex_rect: [
+                left as f32 / size,
+                bottom as f32 / size,
+                (left + width) as f32 / size,
+                (bottom + height) as f32 / size,
+            ],
+            offset: (bounds.min.x, bounds.min.y),
+            size: (width as f32, height as f32),
+        };
+        self.glyphs.insert(key, Some(glyph));
+        Ok(Some(glyph))
+    }
+
+    /// Lays out all text of the frame for an output of the given size in pixels,
+    /// adding missing glyphs to the atlas.
+    pub fn layout(&mut self, frame: &TextFrame, output_size: (u32, u32)) -> Vec<Quad> {
+        if self.font_id != Some(frame.font_id) {
+            self.clear();
+            self.font_id = Some(frame.font_id);
+        }
+        match self.layout_texts(frame, output_size) {
+            Ok(quads) => quads,
+            Err(AtlasFull) => {
+                // make room for the glyphs of this frame, if they still do not fit nothing is drawn
+                self.clear();
+                self.layout_texts(frame, output_size).unwrap_or_default()
+            }
+        }
+    }
+
+    fn layout_texts(
+        &mut self,
+        frame: &TextFrame,
+        output_size: (u32, u32),
+    ) -> Result<Vec<Quad>, AtlasFull> {
+        let mut quads = Vec::new();
+        for text in frame.texts.iter() {
+            self.layout_text(&frame.font, text, output_size, &mut quads)?;
+        }
+        Ok(quads)
+    }
+
+    fn layout_text(
+        &mut self,
+        font: &FontArc,
+        text: &TextDraw,
+        output_size: (u32, u32),
+        quads: &mut Vec<Quad>,
+    ) -> Result<(), AtlasFull> {
+        let overlay = &text.overlay;
+        let (width, height) = (output_size.0 as f32, output_size.1 as f32);
+        let pixel_size = (overlay.size * height)
+            .round()
+            .max(MIN_PIXEL_SIZE)
+            .min(MAX_PIXEL_SIZE);
+        let scaled = font.as_scaled(PxScale::from(pixel_size));
+        let line_height = scaled.height() + scaled.line_gap();
+
+        let lines = overlay
+            .text
+            .lines()
+            .map(|line| {
+                let ids = line.chars().map(|c| font.glyph_id(c)).collect::<Vec<_>>();
+                let width = ids
+                    .iter()
+                    .enumerate()
+                    .map(|(i, id)| {
+                        let kern = if i > 0 { scaled.kern(ids[i - 1], *id) } else { 0.0 };
+                        scaled.h_advance(*id) + kern
+                    })
+                    .sum::<f32>();
+                (ids, width)
+            })
+            .collect::<Vec<_>>();
+        let widest = lines.iter().map(|(_, w)| *w).fold(0.0, f32::max);
+        let box_width = match overlay.max_width {
+            Some(max_width) => widest.min(max_width * width),
+            None => widest,
+        };
+        let box_height = lines.len() as f32 * line_height - scaled.line_gap();
+        let left = overlay.position.0 * width - overlay.anchor.0 * box_width;
+        let top = overlay.position.1 * height - overlay.anchor.1 * box_height;
+        let color = [
+            overlay.color[0],
+            overlay.color[1],
+            overlay.color[2],
+            overlay.color[3] * text.alpha,
+        ];
+
+        for (index, (ids, line_width)) in lines.iter().enumerate() {
+            let baseline = top + index as f32 * line_height + scaled.ascent();
+            let scrolls = *line_width > box_width;
+            let starts = line_starts(
+                left,
+                box_width,
+                *line_width,
+                overlay.anchor.0,
+                line_height,
+                text.scroll_time,
+            );
+            for start in starts {
+                let mut caret = start;
+                for (i, id) in ids.iter().enumerate() {
+                    if i > 0 {
+                        caret += scaled.kern(ids[i - 1], *id);
+                    }
+                    if let Some(glyph) = self.glyph(font, *id, pixel_size)? {
+                        let x = (caret + glyph.offset.0).round();
+                        let y = (baseline + glyph.offset.1).round();
+                        let quad = Quad {
+                            rect: [
+                                x / width,
+                                y / height,
+                                (x + glyph.size.0) / width,
+                                (y + glyph.size.1) / height,
+                            ],
+                            tex_rect: glyph.tex_rect,
+                            color: color,
+                        };
+                        let quad = if scrolls {
+                            quad.clip_horizontally(left / width, (left + box_width) / width)
+                        } else {
+                            Some(quad)
+                        };
+                        quads.extend(quad);
+                    }
+                    caret += scaled.h_advance(*id);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shown(fade: f32, hidden_at: Option<f32>) -> ShownText {
+        ShownText {
+            name: "title".to_string(),
+            overlay: Arc::new(TextOverlay {
+                text: "Title".to_string(),
+                position: (0.0, 0.0),
+                anchor: (0.0, 0.0),
+                size: 0.05,
+                color: [1.0; 4],
+                fade: fade,
+                max_width: None,
+            }),
+            shown_at: 10.0,
+            hidden_at: hidden_at,
+        }
+    }
+
+    #[test]
+    fn fades_in_and_out() {
+        let text = shown(2.0, Some(20.0));
+        assert_eq!(text.alpha(10.0), 0.0);
+        assert_eq!(text.alpha(11.0), 0.5);
+        assert_eq!(text.alpha(15.0), 1.0);
+        assert_eq!(text.alpha(21.5), 0.25);
+        assert!(!text.faded_out(21.5));
+        assert_eq!(text.alpha(22.0), 0.0);
+        assert!(text.faded_out(22.0));
+    }
+
+    #[test]
+    fn hiding_while_fading_in_is_faded_out_in_time() {
+        let text = shown(2.0, Some(11.0));
+        assert_eq!(text.alpha(11.0), 0.5);
+        assert_eq!(text.alpha(12.5), 0.25);
+        assert!(!text.faded_out(12.5));
+        assert!(text.faded_out(13.0));
+    }
+
+    #[test]
+    fn without_fade_text_appears_and_vanishes_at_once() {
+        let text = shown(0.0, None);
+        assert_eq!(text.alpha(10.0), 1.0);
+        assert!(!text.faded_out(100.0));
+        let text = shown(0.0, Some(20.0));
+        assert!(text.faded_out(20.0));
+    }
+
+    #[test]
+    fn aligns_short_lines_within_the_box() {
+        assert_eq!(line_starts(100.0, 50.0, 30.0, 0.0, 10.0, 0.0), vec![100.0]);
+        assert_eq!(line_starts(100.0, 50.0, 30.0, 0.5, 10.0, 0.0), vec![110.0]);
+        assert_eq!(line_starts(100.0, 50.0, 30.0, 1.0, 10.0, 0.0), vec![120.0]);
+    }
+
+    #[test]
+    fn scrolls_long_lines_after_a_delay() {
+        let period = 80.0 + MARQUEE_GAP * 10.0;
+        assert_eq!(line_starts(0.0, 50.0, 80.0, 1.0, 10.0, 0.0), vec![0.0, period]);
+        assert_eq!(
+            line_starts(0.0, 50.0, 80.0, 1.0, 10.0, MARQUEE_DELAY),
+            vec![0.0, period]
+        );
+        let scrolled = MARQUEE_SPEED * 10.0;
+        assert_eq!(
+            line_starts(0.0, 50.0, 80.0, 1.0, 10.0, MARQUEE_DELAY + 1.0),
+            vec![-scrolled, period - scrolled]
+        );
+        // wraps around once the repetition reached the start
+        let wrapped = line_starts(0.0, 50.0, 80.0, 1.0, 10.0, MARQUEE_DELAY + period / scrolled);
+        assert!(wrapped[0].abs() < 1e-3, "{:?}", wrapped);
+    }
+}
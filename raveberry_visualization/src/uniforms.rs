@@ -0,0 +1,160 @@
+use crate::images::{self, ImageTextures, SlotUniform};
+use crate::output;
+use glium::uniforms::{UniformType, UniformValue, Uniforms};
+use std::collections::HashMap;
+
+/// Whether the uniform is supplied by the visualization itself and can not be set by the user.
+pub fn is_builtin(name: &str) -> bool {
+    [
+        output::BACKGROUND_UNIFORMS,
+        output::PARTICLE_UNIFORMS,
+        output::FOREGROUND_UNIFORMS,
+        output::DIM_UNIFORMS,
+    ]
+    .iter()
+    .any(|uniforms| uniforms.iter().any(|(n, _)| *n == name))
+}
+
+/// A value for a variant specific uniform, set through `Controller.set_uniform`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UserUniform {
+    Float(f32),
+    Int(i32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+}
+
+impl UserUniform {
+    /// Whether the value can be bound to a uniform declared with the given type.
+    pub fn fits(&self, ty: UniformType) -> bool {
+        self.value_for(ty).is_some()
+    }
+
+    /// The GLSL type the value was given as.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            UserUniform::Float(_) => "float",
+            UserUniform::Int(_) => "int",
+            UserUniform::Vec2(_) => "vec2",
+            UserUniform::Vec3(_) => "vec3",
+            UserUniform::Vec4(_) => "vec4",
+        }
+    }
+
+    /// Converts the value to the type the program declared, None if that is not possible.
+    fn value_for(&self, ty: UniformType) -> Option<UniformValue<'static>> {
+        match (*self, ty) {
+            (UserUniform::Float(value), UniformType::Float) => Some(UniformValue::Float(value)),
+            (UserUniform::Int(value), UniformType::Int) => Some(UniformValue::SignedInt(value)),
+            (UserUniform::Int(value), UniformType::UnsignedInt) if value >= 0 => {
+                Some(UniformValue::UnsignedInt(value as u32))
+            }
+            (UserUniform::Int(value), UniformType::Float) => {
+                Some(UniformValue::Float(value as f32))
+            }
+            (UserUniform::Int(value), UniformType::Bool) => Some(UniformValue::Bool(value != 0)),
+            (UserUniform::Vec2(value), UniformType::FloatVec2) => Some(UniformValue::Vec2(value)),
+            (UserUniform::Vec3(value), UniformType::FloatVec3) => Some(UniformValue::Vec3(value)),
+            (UserUniform::Vec4(value), UniformType::FloatVec4) => Some(UniformValue::Vec4(value)),
+            _ => None,
+        }
+    }
+}
+
+/// The name of a uniform type as written in GLSL.
+pub fn type_name(ty: UniformType) -> String {
+    match ty {
+        UniformType::Float => "float".to_string(),
+        UniformType::FloatVec2 => "vec2".to_string(),
+        UniformType::FloatVec3 => "vec3".to_string(),
+        UniformType::FloatVec4 => "vec4".to_string(),
+        UniformType::Int => "int".to_string(),
+        UniformType::UnsignedInt => "uint".to_string(),
+        UniformType::Bool => "bool".to_string(),
+        UniformType::Sampler2d => "sampler2D".to_string(),
+        ty => format!("{:?}", ty),
+    }
+}
+
+/// The uniforms a program declares that are not supplied by the visualization
+/// and can be set with `set_uniform`.
+pub fn declared_user_uniforms(
+    program: &glium::Program,
+    image_slots: &[String],
+) -> Vec<(String, UniformType)> {
+    program
+        .uniforms()
+        .filter(|(name, _)| !is_builtin(name))
+        .filter(|(name, _)| images::slot_of(name, image_slots).is_none())
+        .map(|(name, uniform)| (name.clone(), uniform.ty))
+        .collect()
+}
+
+/// Extends the uniforms of a pass with the user uniforms and image slots the program declares.
+/// Values whose type does not match the declaration are skipped instead of failing the draw.
+pub struct WithUserUniforms<'a, U: Uniforms> {
+    pub uniforms: U,
+    pub program: &'a glium::Program,
+    pub user_uniforms: &'a HashMap<String, UserUniform>,
+    pub images: &'a ImageTextures,
+    /// the image slots listed in the manifest of the variant
+    pub image_slots: &'a [String],
+    /// the transition of every slot that changed, slots missing here are fully shown
+    pub image_transitions: &'a HashMap<String, f32>,
+}
+
+impl<'a, U: Uniforms> Uniforms for WithUserUniforms<'a, U> {
+    fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut output: F) {
+        self.uniforms.visit_values(&mut output);
+        for (name, uniform) in self.program.uniforms() {
+            if is_builtin(name) {
+                continue;
+            }
+            match images::slot_of(name, self.image_slots) {
+                Some((_, kind)) if kind.ty() != uniform.ty => (),
+                Some((slot, SlotUniform::Transition)) => {
+                    let transition = self.image_transitions.get(slot).copied().unwrap_or(1.0);
+                    output(name, UniformValue::Float(transition));
+                }
+                Some((slot, kind)) => {
+                    let previous = kind == SlotUniform::Previous;
+                    let (texture, behavior) = self.images.texture(slot, previous);
+                    output(name, UniformValue::Texture2d(texture, Some(behavior)));
+                }
+                None => {
+                    if let Some(value) = self.user_uniforms.get(name) {
+                        if let Some(value) = value.value_for(uniform.ty) {
+                            output(name, value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_fit_their_declared_types() {
+        assert!(UserUniform::Float(1.0).fits(UniformType::Float));
+        assert!(UserUniform::Int(1).fits(UniformType::Float));
+        assert!(UserUniform::Int(1).fits(UniformType::UnsignedInt));
+        assert!(!UserUniform::Int(-1).fits(UniformType::UnsignedInt));
+        assert!(UserUniform::Vec3([1.0; 3]).fits(UniformType::FloatVec3));
+        assert!(!UserUniform::Float(1.0).fits(UniformType::FloatVec3));
+        assert!(!UserUniform::Vec2([1.0; 2]).fits(UniformType::FloatVec3));
+        assert!(!UserUniform::Float(1.0).fits(UniformType::Int));
+    }
+
+    #[test]
+    fn builtin_uniforms() {
+        assert!(is_builtin("RESOLUTION"));
+        assert!(is_builtin("logo"));
+        assert!(is_builtin("brightness"));
+        assert!(!is_builtin("speed"));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::images;
+use crate::output;
+use crate::shaders::Programs;
+use crate::variants::Variant;
+use crate::{Particle, Vertex};
+use glium::backend::Facade;
+use glium::uniforms::UniformType;
+use glium::vertex::{AttributeType, Vertex as _, VertexFormat};
+use pyo3::prelude::*;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    /// the pass draws, but probably not as intended
+    Warning,
+    /// drawing the pass would fail
+    Error,
+}
+
+/// A mismatch between what a program declares and what its pass supplies.
+#[derive(Clone, Debug)]
+pub struct Issue {
+    pub severity: Severity,
+    /// None if the issue concerns the variant as a whole
+    pub pass: Option<&'static str>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match self.pass {
+            Some(pass) => write!(f, "{} in {} pass: {}", severity, pass, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+/// The uniforms and attributes a pass supplies to its program.
+pub struct PassInterface {
+    pub name: &'static str,
+    pub uniforms: &'static [(&'static str, UniformType)],
+    pub attributes: Vec<(String, AttributeType)>,
+}
+
+// the attributes of the vertex formats a pass is drawn with
+fn attributes(formats: &[VertexFormat]) -> Vec<(String, AttributeType)> {
+    formats
+        .iter()
+        .flat_map(|format| format.iter())
+        .map(|(name, _, _, ty, _)| (name.to_string(), *ty))
+        .collect()
+}
+
+pub fn background() -> PassInterface {
+    PassInterface {
+        name: "background",
+        uniforms: output::BACKGROUND_UNIFORMS,
+        attributes: attributes(&[Vertex::build_bindings()]),
+    }
+}
+
+pub fn particles() -> PassInterface {
+    PassInterface {
+        name: "particles",
+        uniforms: output::PARTICLE_UNIFORMS,
+        attributes: attributes(&[Vertex::build_bindings(), Particle::build_bindings()]),
+    }
+}
+
+pub fn foreground() -> PassInterface {
+    PassInterface {
+        name: "foreground",
+        uniforms: output::FOREGROUND_UNIFORMS,
+        attributes: attributes(&[Vertex::build_bindings()]),
+    }
+}
+
+// supplied uniforms that variants are not expected to use
+const OPTIONAL_UNIFORMS: &[&str] = &["quality", "BARS", "logo_alpha"];
+
+/// Compares the reflected interface of every program with what its pass supplies.
+/// `user_uniforms` are the names set through `set_uniform`, which are expected to be declared,
+/// `image_slots` the slots listed in the manifest of the variant.
+///
+/// Uniforms the driver optimized away are not reported by reflection,
+/// so a uniform that is declared but unused counts as not declared.
+pub fn check(
+    passes: &[(&PassInterface, &glium::Program)],
+    user_uniforms: &[String],
+    image_slots: &[String],
+) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    for (pass, program) in passes {
+        for (name, uniform) in program.uniforms() {
+            match pass.uniforms.iter().find(|(n, _)| n == name) {
+                Some((_, ty)) if *ty != uniform.ty => issues.push(Issue {
+                    severity: Severity::Error,
+                    pass: Some(pass.name),
+                    message: format!(
+                        "uniform {} is declared as {:?}, but supplied as {:?}",
+                        name, uniform.ty, ty
+                    ),
+                }),
+                Some(_) => (),
+                None if user_uniforms.contains(name) => (),
+                None => match images::slot_of(name, image_slots) {
+                    Some((slot, kind)) if kind.ty() != uniform.ty => issues.push(Issue {
+                        severity: Severity::Error,
+                        pass: Some(pass.name),
+                        message: format!(
+                            "uniform {} of image slot {} is declared as {:?}, but supplied as {:?}",
+                            name,
+                            slot,
+                            uniform.ty,
+                            kind.ty()
+                        ),
+                    }),
+                    Some(_) => (),
+                    None => issues.push(Issue {
+                        severity: Severity::Warning,
+                        pass: Some(pass.name),
+                        message: format!(
+                            "uniform {} is not supplied and stays zero unless it is set with set_uniform",
+                            name
+                        ),
+                    }),
+                },
+            }
+        }
+        for (name, attribute) in program.attributes() {
+            let message = match pass.attributes.iter().find(|(n, _)| n == name) {
+                Some((_, ty)) if *ty != attribute.ty => format!(
+                    "attribute {} is declared as {:?}, but supplied as {:?}",
+                    name, attribute.ty, ty
+                ),
+                Some(_) => continue,
+                None => format!("attribute {} is not supplied", name),
+            };
+            issues.push(Issue {
+                severity: Severity::Error,
+                pass: Some(pass.name),
+                message: message,
+            });
+        }
+    }
+
+    // a uniform being unused in a single pass is normal, but if no pass of the variant uses it,
+    // it was most likely misspelled or forgotten
+    let mut supplied = Vec::new();
+    for (pass, _) in passes {
+        for (name, _) in pass.uniforms {
+            if !supplied.contains(name) {
+                supplied.push(*name);
+            }
+        }
+    }
+    for name in supplied {
+        let used = passes
+            .iter()
+            .any(|(pass, program)| {
+                pass.uniforms.iter().any(|(n, _)| *n == name) && program.get_uniform(name).is_some()
+            });
+        // supporting lower quality tiers or hiding the logo is optional,
+        // and BARS is only a constant for convenience
+        if !used && !OPTIONAL_UNIFORMS.contains(&name) {
+            issues.push(Issue {
+                severity: Severity::Warning,
+                pass: None,
+                message: format!("uniform {} is supplied but not used", name),
+            });
+        }
+    }
+    for slot in image_slots {
+        let used = passes.iter().any(|(_, program)| {
+            program.uniforms().any(|(name, _)| {
+                images::slot_of(name, image_slots).map_or(false, |(s, _)| s == slot)
+            })
+        });
+        if !used {
+            issues.push(Issue {
+                severity: Severity::Warning,
+                pass: None,
+                message: format!("image slot {} is listed in the manifest but not used", slot),
+            });
+        }
+    }
+    issues
+}
+
+/// The result of compiling a single variant, as reported to python.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct VariantReport {
+    #[pyo3(get)]
+    pub variant: String,
+    /// whether the variant compiled and matches the interface of the visualization
+    #[pyo3(get)]
+    pub passed: bool,
+    /// the GLSL log if reading, compiling or linking the shaders failed
+    #[pyo3(get)]
+    pub log: Option<String>,
+    #[pyo3(get)]
+    pub errors: Vec<String>,
+    #[pyo3(get)]
+    pub warnings: Vec<String>,
+}
+
+#[pymethods]
+impl VariantReport {
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+/// Compiles the variant and checks the interface of its programs, without drawing anything.
+pub fn validate_variant<F: Facade>(facade: &F, variant: &Variant) -> VariantReport {
+    let programs = match Programs::compile(facade, variant) {
+        Ok(programs) => programs,
+        Err(log) => {
+            return VariantReport {
+                variant: variant.name.clone(),
+                passed: false,
+                log: Some(log),
+                errors: Vec::new(),
+                warnings: Vec::new(),
+            }
+        }
+    };
+    let issues = programs.validate(&[]);
+    let messages = |severity: Severity| {
+        issues
+            .iter()
+            .filter(|i| i.severity == severity)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+    };
+    let errors = messages(Severity::Error);
+    VariantReport {
+        variant: variant.name.clone(),
+        passed: errors.is_empty(),
+        log: None,
+        errors: errors,
+        warnings: messages(Severity::Warning),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attribute_names(pass: PassInterface) -> Vec<String> {
+        pass.attributes.into_iter().map(|(name, _)| name).collect()
+    }
+
+    #[test]
+    fn attributes_follow_the_vertex_formats() {
+        assert_eq!(attribute_names(background()), vec!["position"]);
+        assert_eq!(
+            attribute_names(particles()),
+            vec!["position", "translation", "start_z", "speed"]
+        );
+        assert_eq!(attribute_names(foreground()), vec!["position"]);
+    }
+
+    #[test]
+    fn optional_uniforms_are_supplied() {
+        for name in OPTIONAL_UNIFORMS {
+            assert!(crate::uniforms::is_builtin(name), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::assets;
+use pyo3::prelude::*;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The files a directory needs to contain to be a variant.
+pub const SHADER_FILES: &[&str] = &[
+    "quad.vs",
+    "background.fs",
+    "foreground.fs",
+    "particle.vs",
+    "particle.fs",
+];
+
+/// A visualization that can be passed to `start`.
+///
+/// Variants can describe themselves in an optional `variant.toml`:
+/// ```toml
+/// description = "A ring around the logo"
+/// # relative to the variant directory, preview.png is used if it exists
+/// preview = "preview.png"
+/// # user uniforms that need to be set with set_uniform
+/// uniforms = ["speed"]
+/// # image slots the shaders show, filled with set_image
+/// images = ["cover"]
+/// ```
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct Variant {
+    #[pyo3(get)]
+    pub name: String,
+    /// the directory containing the shaders, None for variants built into the library
+    #[pyo3(get)]
+    pub path: Option<String>,
+    #[pyo3(get)]
+    pub description: String,
+    /// path of an image showing the variant, if it has one
+    #[pyo3(get)]
+    pub preview: Option<String>,
+    #[pyo3(get)]
+    pub required_uniforms: Vec<String>,
+    /// the slots of images shown by the variant, see `Controller.set_image`
+    #[pyo3(get)]
+    pub image_slots: Vec<String>,
+}
+
+#[pymethods]
+impl Variant {
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+impl Variant {
+    /// Reads the source of one of the `SHADER_FILES` of the variant.
+    pub fn read_shader(&self, file: &str) -> Result<String, String> {
+        match &self.path {
+            Some(directory) => {
+                let path = Path::new(directory).join(file);
+                fs::read_to_string(&path)
+                    .map_err(|e| format!("Could not read {}: {}", path.display(), e))
+            }
+            None => assets::builtin_variant(&self.name)
+                .and_then(|variant| {
+                    let index = SHADER_FILES.iter().position(|f| *f == file)?;
+                    Some(variant.shaders[index].to_string())
+                })
+                .ok_or_else(|| format!("{} has no builtin {}", self.name, file)),
+        }
+    }
+}
+
+fn is_complete(directory: &Path) -> bool {
+    SHADER_FILES.iter().all(|file| directory.join(file).is_file())
+}
+
+fn string_array(key: &str, values: &[toml::Value]) -> Result<Vec<String>, String> {
+    values
+        .iter()
+        .map(|value| value.as_str().map(|v| v.to_string()))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| format!("{}: expected an array of strings", key))
+}
+
+// The preview is relative to the directory, builtin variants have none.
+fn parse_manifest(text: &str, directory: Option<&Path>, variant: &mut Variant) -> Result<(), String> {
+    let manifest = toml::from_str::<toml::value::Table>(&text).map_err(|e| e.to_string())?;
+    for (key, value) in manifest.iter() {
+        match (key.as_str(), value) {
+            ("description", toml::Value::String(description)) => {
+                variant.description = description.clone()
+            }
+            ("preview", toml::Value::String(preview)) => {
+                variant.preview =
+                    directory.map(|d| d.join(preview).to_string_lossy().into_owned())
+            }
+            ("uniforms", toml::Value::Array(uniforms)) => {
+                variant.required_uniforms = string_array(key, uniforms)?
+            }
+            ("images", toml::Value::Array(slots)) => {
+                variant.image_slots = string_array(key, slots)?
+            }
+            ("description", _) | ("preview", _) => {
+                return Err(format!("{}: expected a string", key))
+            }
+            ("uniforms", _) | ("images", _) => {
+                return Err(format!("{}: expected an array of strings", key))
+            }
+            _ => return Err(format!("{}: unknown key", key)),
+        }
+    }
+    Ok(())
+}
+
+fn read_manifest(directory: &Path, variant: &mut Variant) -> Result<(), String> {
+    let path = directory.join("variant.toml");
+    if !path.is_file() {
+        return Ok(());
+    }
+    let text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    parse_manifest(&text, Some(directory), variant)
+}
+
+/// Lists the complete variants in the given shader directory, sorted by name.
+pub fn list(shader_directory: &Path) -> Result<Vec<Variant>, String> {
+    let entries = fs::read_dir(shader_directory)
+        .map_err(|e| format!("Could not read {}: {}", shader_directory.display(), e))?;
+    let mut variants = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let directory = entry.path();
+        // skips stray files as well as hidden and incomplete directories
+        if name.starts_with('.') || !is_complete(&directory) {
+            continue;
+        }
+        let preview = directory.join("preview.png");
+        let mut variant = Variant {
+            name: name,
+            path: Some(directory.to_string_lossy().into_owned()),
+            description: String::new(),
+            preview: if preview.is_file() {
+                Some(preview.to_string_lossy().into_owned())
+            } else {
+                None
+            },
+            required_uniforms: Vec::new(),
+            image_slots: Vec::new(),
+        };
+        // a broken manifest does not make the shaders unusable
+        let mut described = variant.clone();
+        match read_manifest(&directory, &mut described) {
+            Ok(()) => variant = described,
+            Err(e) => eprintln!("Ignoring {}: {}", directory.join("variant.toml").display(), e),
+        }
+        variants.push(variant);
+    }
+    variants.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(variants)
+}
+
+/// The directory users can install their own variants to,
+/// `$XDG_DATA_HOME/raveberry/visualizations` or its default in the home directory.
+pub fn user_directory() -> Option<PathBuf> {
+    let data_home = match env::var_os("XDG_DATA_HOME") {
+        Some(data_home) if !data_home.is_empty() => PathBuf::from(data_home),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(data_home.join("raveberry/visualizations"))
+}
+
+/// The variants compiled into the library.
+pub fn builtin() -> Vec<Variant> {
+    assets::BUILTIN_VARIANTS
+        .iter()
+        .map(|builtin| {
+            let mut variant = Variant {
+                name: builtin.name.to_string(),
+                path: None,
+                description: String::new(),
+                preview: None,
+                required_uniforms: Vec::new(),
+                image_slots: Vec::new(),
+            };
+            // the manifests are checked by loading them from the package directory
+            let _ = parse_manifest(builtin.manifest, None, &mut variant);
+            variant
+        })
+        .collect()
+}
+
+/// Merges the variants found in all search paths with the builtin ones, sorted by name.
+/// If several paths contain a variant with the same name, the earlier path takes precedence,
+/// and variants on disk take precedence over builtin ones.
+/// Paths that cannot be read are skipped, with a warning if they are marked as required.
+pub fn search(paths: &[(PathBuf, bool)]) -> Vec<Variant> {
+    let mut variants: Vec<Variant> = Vec::new();
+    for (path, required) in paths {
+        if !required && !path.is_dir() {
+            continue;
+        }
+        let listed = match list(path) {
+            Ok(listed) => listed,
+            Err(e) => {
+                eprintln!("Skipping variant path: {}", e);
+                continue;
+            }
+        };
+        for variant in listed {
+            if variants.iter().all(|v| v.name != variant.name) {
+                variants.push(variant);
+            }
+        }
+    }
+    for variant in builtin() {
+        if variants.iter().all(|v| v.name != variant.name) {
+            variants.push(variant);
+        }
+    }
+    variants.sort_by(|a, b| a.name.cmp(&b.name));
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant() -> Variant {
+        Variant {
+            name: "Test".to_string(),
+            path: None,
+            description: String::new(),
+            preview: None,
+            required_uniforms: Vec::new(),
+            image_slots: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parses_image_slots() {
+        let mut variant = variant();
+        parse_manifest("images = [\"cover\", \"artist\"]", None, &mut variant).unwrap();
+        assert_eq!(variant.image_slots, vec!["cover", "artist"]);
+    }
+
+    #[test]
+    fn rejects_invalid_image_slots() {
+        let mut variant = variant();
+        let error = parse_manifest("images = \"cover\"", None, &mut variant).unwrap_err();
+        assert_eq!(error, "images: expected an array of strings");
+        let error = parse_manifest("images = [1]", None, &mut variant).unwrap_err();
+        assert_eq!(error, "images: expected an array of strings");
+    }
+
+    #[test]
+    fn builtin_variants_declare_the_cover() {
+        for variant in builtin() {
+            assert_eq!(variant.image_slots, vec!["cover"], "{}", variant.name);
+        }
+    }
+
+    #[test]
+    fn unreadable_paths_keep_the_builtin_variants() {
+        let missing = PathBuf::from("/nonexistent/raveberry/visualizations");
+        let names = |variants: Vec<Variant>| -> Vec<String> {
+            variants.into_iter().map(|v| v.name).collect()
+        };
+        assert_eq!(names(search(&[(missing, true)])), names(search(&[])));
+        assert!(!search(&[]).is_empty());
+    }
+}